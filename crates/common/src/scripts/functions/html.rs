@@ -24,6 +24,7 @@
 use std::borrow::Cow;
 
 use mail_parser::decoders::html::{add_html_token, html_to_text};
+use nlp::bayes::features::image_tokens;
 use sieve::{runtime::Variable, Context};
 
 pub fn fn_html_to_text<'x>(_: &'x Context<'x>, v: Vec<Variable>) -> Variable {
@@ -78,11 +79,28 @@ pub fn fn_html_attr<'x>(_: &'x Context<'x>, v: Vec<Variable>) -> Variable {
         .unwrap_or_default()
 }
 
+pub const HTML_MAX_NESTED_LEVELS: usize = 128;
+pub const HTML_TOO_DEEP_TOKEN: &str = "__HTML_TOO_DEEP__";
+
+const VOID_ELEMENTS: &[&[u8]] = &[
+    b"area", b"base", b"br", b"col", b"embed", b"hr", b"img", b"input", b"link", b"meta", b"param",
+    b"source", b"track", b"wbr",
+];
+
 pub fn html_to_tokens(input: &str) -> Vec<Variable> {
+    html_to_tokens_with_depth(input, HTML_MAX_NESTED_LEVELS)
+}
+
+/// Tokenizes HTML dropping the tags and text nested more than `max_depth`
+/// elements deep, which are replaced by a single `HTML_TOO_DEEP_TOKEN`.
+pub fn html_to_tokens_with_depth(input: &str, max_depth: usize) -> Vec<Variable> {
     let input = input.as_bytes();
     let mut iter = input.iter().enumerate();
     let mut tags = vec![];
 
+    let mut depth = 0;
+    let mut is_too_deep = false;
+
     let mut is_token_start = true;
     let mut is_after_space = false;
     let mut is_new_line = true;
@@ -105,7 +123,9 @@ pub fn html_to_tokens(input: &str) -> Vec<Variable> {
                     is_token_start = true;
                 }
                 if text.len() > 1 {
-                    tags.push(Variable::String(text.into()));
+                    if depth <= max_depth {
+                        tags.push(Variable::String(text.into()));
+                    }
                     text = String::from("_");
                 }
 
@@ -169,6 +189,26 @@ pub fn html_to_tokens(input: &str) -> Vec<Variable> {
                         last_ch = ch;
                     }
                 }
+                match tag_nesting(&tag) {
+                    Nesting::Open => {
+                        depth += 1;
+                        if depth > max_depth {
+                            if !is_too_deep {
+                                tags.push(Variable::from(HTML_TOO_DEEP_TOKEN));
+                                is_too_deep = true;
+                            }
+                            continue;
+                        }
+                    }
+                    Nesting::Close => {
+                        depth = depth.saturating_sub(1);
+                        if depth >= max_depth {
+                            continue;
+                        }
+                    }
+                    Nesting::None if depth > max_depth => continue,
+                    Nesting::None => (),
+                }
                 tags.push(Variable::String(
                     String::from_utf8(tag).unwrap_or_default().into(),
                 ));
@@ -225,13 +265,41 @@ pub fn html_to_tokens(input: &str) -> Vec<Variable> {
             is_after_space && !is_new_line,
         );
     }
-    if text.len() > 1 {
+    if text.len() > 1 && depth <= max_depth {
         tags.push(Variable::String(text.into()));
     }
 
     tags
 }
 
+enum Nesting {
+    Open,
+    Close,
+    None,
+}
+
+fn tag_nesting(tag: &[u8]) -> Nesting {
+    let (name, is_close) = match tag.get(1) {
+        Some(b'/') => (&tag[2..], true),
+        Some(ch) if ch.is_ascii_alphabetic() => (&tag[1..], false),
+        _ => return Nesting::None,
+    };
+    let name = name
+        .split(|ch| matches!(ch, b' ' | b'/'))
+        .next()
+        .unwrap_or_default();
+
+    if VOID_ELEMENTS.contains(&name) {
+        Nesting::None
+    } else if is_close {
+        Nesting::Close
+    } else if tag.ends_with(b"/") {
+        Nesting::None
+    } else {
+        Nesting::Open
+    }
+}
+
 pub fn html_attr_tokens(input: &str, tag: &str, attrs: Vec<Cow<str>>) -> Vec<Variable> {
     let input = input.as_bytes();
     let mut iter = input.iter().enumerate().peekable();
@@ -357,6 +425,17 @@ pub fn html_img_area(arr: &[Variable]) -> u32 {
         .sum::<u32>()
 }
 
+/// Returns the classifier tokens for an `<img>` tag as produced by
+/// `html_to_tokens`, including its alt text and, when available, a
+/// signature of the image bytes.
+pub fn html_img_tokens(tag: &str, image: Option<&[u8]>) -> Vec<Cow<'static, str>> {
+    if tag.starts_with("<img") {
+        image_tokens(get_attribute(tag, "alt"), image)
+    } else {
+        vec![]
+    }
+}
+
 pub fn get_attribute<'x>(tag: &'x str, attr_name: &str) -> Option<&'x str> {
     let tag = tag.as_bytes();
     let attr_name = attr_name.as_bytes();
@@ -437,3 +516,76 @@ pub fn get_attribute<'x>(tag: &'x str, attr_name: &str) -> Option<&'x str> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{html_img_tokens, html_to_tokens, html_to_tokens_with_depth, HTML_TOO_DEEP_TOKEN};
+
+    #[test]
+    fn img_tokens() {
+        let tokens = html_to_tokens(r#"<p>Hello <IMG SRC="cid:1" ALT="Cheap Pills" width=10></p>"#);
+        let img = tokens
+            .iter()
+            .map(|t| t.to_string())
+            .find(|t| t.starts_with("<img"))
+            .unwrap();
+
+        assert_eq!(
+            html_img_tokens(img.as_ref(), None),
+            vec!["img:present", "img:alt:cheap", "img:alt:pills"]
+        );
+
+        let with_bytes = html_img_tokens(img.as_ref(), Some(b"GIF89a"));
+        assert_eq!(&with_bytes[3..5], &["img:format:gif", "img:size:tiny"]);
+        assert!(with_bytes[5].starts_with("img:hash:"));
+
+        assert!(html_img_tokens("<p>", None).is_empty());
+        assert_eq!(
+            html_img_tokens("<img src=\"a.png\">", None),
+            vec!["img:present"]
+        );
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let html = format!(
+            "<html><b>shallow</b>{}deep{}<p>after<br></p></html>",
+            "<div>".repeat(100_000),
+            "</div>".repeat(100_000)
+        );
+        let tokens = html_to_tokens_with_depth(&html, 16)
+            .into_iter()
+            .map(|t| t.to_string().into_owned())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| t.as_str() == HTML_TOO_DEEP_TOKEN)
+                .count(),
+            1
+        );
+        assert!(!tokens
+            .iter()
+            .any(|t| t.contains("deep") && t != HTML_TOO_DEEP_TOKEN));
+        assert_eq!(tokens.iter().filter(|t| t.as_str() == "<div").count(), 15);
+        assert_eq!(tokens.iter().filter(|t| t.as_str() == "</div").count(), 15);
+        assert!(tokens.ends_with(&[
+            "<p".to_string(),
+            "_after".to_string(),
+            "<br".to_string(),
+            "</p".to_string(),
+            "</html".to_string()
+        ]));
+        assert!(tokens.contains(&"_shallow".to_string()));
+
+        // Documents within the limit are not affected
+        assert_eq!(
+            html_to_tokens("<div><p>Hello</p></div>"),
+            html_to_tokens_with_depth("<div><p>Hello</p></div>", 2)
+        );
+        assert!(!html_to_tokens_with_depth("<div><p>Hello</p></div>", 1)
+            .iter()
+            .any(|t| t.to_string() == "_Hello"));
+    }
+}
@@ -228,3 +228,61 @@ impl<T> GetRequest<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{json::Parser, JsonObjectParser},
+        request::{method::MethodObject, reference::MaybeReference},
+        types::{any_id::AnyId, id::Id, property::Property},
+    };
+
+    use super::{GetRequest, RequestArguments};
+
+    #[test]
+    fn parse_sieve_script_get() {
+        let parse = |json: &str| {
+            let mut parser = Parser::new(json.as_bytes());
+            parser.ctx = MethodObject::SieveScript;
+            GetRequest::<RequestArguments>::parse(&mut parser).unwrap()
+        };
+        let account_id = Id::new(7);
+
+        // Explicit id list, unknown keys are skipped
+        let request = parse(&format!(
+            r#"{{"accountId": "{account_id}", "ids": ["{}", "{}"], "properties": ["name", "isActive"], "other": {{"ids": []}}}}"#,
+            Id::new(1),
+            Id::new(2)
+        ));
+        assert!(matches!(request.arguments, RequestArguments::SieveScript));
+        assert_eq!(request.account_id, account_id);
+        assert_eq!(
+            request.ids,
+            Some(MaybeReference::Value(vec![
+                MaybeReference::Value(AnyId::Id(Id::new(1))),
+                MaybeReference::Value(AnyId::Id(Id::new(2))),
+            ]))
+        );
+        assert_eq!(
+            request.properties,
+            Some(MaybeReference::Value(vec![
+                Property::Name,
+                Property::IsActive
+            ]))
+        );
+
+        // References are kept for later resolution
+        let request = parse(&format!(
+            r##"{{"accountId": "{account_id}", "#ids": {{"resultOf": "c0", "name": "SieveScript/query", "path": "/ids"}}}}"##
+        ));
+        match request.ids {
+            Some(MaybeReference::Reference(reference)) => {
+                assert_eq!(reference.result_of, "c0");
+                assert_eq!(reference.name.to_string(), "SieveScript/query");
+                assert_eq!(reference.path.item_query(), Some("ids"));
+            }
+            ids => panic!("unexpected ids: {ids:?}"),
+        }
+        assert!(request.properties.is_none());
+    }
+}
@@ -21,19 +21,30 @@
  * for more details.
 */
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::set::SetError,
-    parser::{json::Parser, JsonObjectParser, Token},
-    request::RequestProperty,
+    error::{method::MethodError, set::SetError},
+    parser::{json::Parser, Error, JsonObjectParser, Token},
+    request::{
+        reference::{MaybeReference, ResultReference},
+        RequestProperty,
+    },
     types::{blob::BlobId, id::Id},
 };
 
-#[derive(Debug, Clone)]
+/// Live requests are read with [`JsonObjectParser`], the serde
+/// implementations are only meant for storing requests and only support
+/// resolved blob ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidateSieveScriptRequest {
-    pub account_id: Id,
-    pub blob_id: BlobId,
+    #[serde(rename = "accountId")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<Id>,
+    #[serde(rename = "blobId")]
+    #[serde(with = "resolved_blob_id")]
+    pub blob_id: MaybeReference<BlobId, ResultReference>,
 }
 
 #[derive(Debug, Serialize)]
@@ -41,6 +52,10 @@ pub struct ValidateSieveScriptResponse {
     #[serde(rename = "accountId")]
     pub account_id: Id,
     pub error: Option<SetError>,
+    /// Non-fatal issues found in a script that compiled successfully, `None`
+    /// when there are none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<SetError>>,
 }
 
 impl JsonObjectParser for ValidateSieveScriptRequest {
@@ -49,9 +64,10 @@ impl JsonObjectParser for ValidateSieveScriptRequest {
         Self: Sized,
     {
         let mut request = ValidateSieveScriptRequest {
-            account_id: Id::default(),
-            blob_id: BlobId::default(),
+            account_id: None,
+            blob_id: MaybeReference::Value(BlobId::default()),
         };
+        let mut seen = [false; 2];
 
         parser
             .next_token::<String>()?
@@ -60,13 +76,24 @@ impl JsonObjectParser for ValidateSieveScriptRequest {
         while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
             match &key.hash[0] {
                 0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
-                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                    assert_unique(&mut seen[0], "accountId")?;
+                    request.account_id = parse_account_id(
+                        &parser.next_token::<String>()?.unwrap_string("accountId")?,
+                    )?
+                    .into();
                 }
-                0x6449_626f_6c62 if !key.is_ref => {
-                    request.blob_id = parser.next_token::<BlobId>()?.unwrap_string("blobId")?;
+                0x6449_626f_6c62 => {
+                    assert_unique(&mut seen[1], "blobId")?;
+                    request.blob_id = if !key.is_ref {
+                        MaybeReference::Value(
+                            parser.next_token::<BlobId>()?.unwrap_string("blobId")?,
+                        )
+                    } else {
+                        MaybeReference::Reference(ResultReference::parse(parser)?)
+                    };
                 }
                 _ => {
-                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                    parser.skip_unknown_property()?;
                 }
             }
         }
@@ -74,3 +101,355 @@ impl JsonObjectParser for ValidateSieveScriptRequest {
         Ok(request)
     }
 }
+
+mod resolved_blob_id {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{
+        request::reference::{MaybeReference, ResultReference},
+        types::blob::BlobId,
+    };
+
+    pub fn serialize<S>(
+        blob_id: &MaybeReference<BlobId, ResultReference>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match blob_id {
+            MaybeReference::Value(blob_id) => blob_id.serialize(serializer),
+            MaybeReference::Reference(reference) => Err(serde::ser::Error::custom(format!(
+                "Unresolved blobId reference {reference}."
+            ))),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<MaybeReference<BlobId, ResultReference>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        BlobId::deserialize(deserializer).map(MaybeReference::Value)
+    }
+}
+
+/// Decodes an account id, which must be a document id other than the
+/// `u32::MAX` sentinel used by the store for unset accounts.
+fn parse_account_id(value: &str) -> crate::parser::Result<Id> {
+    Id::from_bytes(value.as_bytes())
+        .filter(|id| !value.is_empty() && id.id() < u32::MAX as u64)
+        .ok_or_else(|| {
+            Error::Method(MethodError::InvalidArguments(format!(
+                "Invalid accountId {value:?}."
+            )))
+        })
+}
+
+fn assert_unique(seen: &mut bool, property: &str) -> crate::parser::Result<()> {
+    if !std::mem::replace(seen, true) {
+        Ok(())
+    } else {
+        Err(Error::Method(MethodError::InvalidArguments(format!(
+            "Duplicate argument {property:?}."
+        ))))
+    }
+}
+
+impl ValidateSieveScriptRequest {
+    /// Builds a request without going through the JSON parser.
+    ///
+    /// ```
+    /// use jmap_proto::{
+    ///     method::validate::ValidateSieveScriptRequest,
+    ///     request::reference::MaybeReference,
+    ///     types::{blob::BlobId, id::Id},
+    /// };
+    ///
+    /// let request = ValidateSieveScriptRequest::new(Id::new(1), BlobId::default())
+    ///     .with_account_id(Id::new(7));
+    /// assert_eq!(request.account_id, Some(Id::new(7)));
+    /// assert_eq!(request.blob_id, MaybeReference::Value(BlobId::default()));
+    /// ```
+    pub fn new(account_id: Id, blob_id: BlobId) -> Self {
+        ValidateSieveScriptRequest {
+            account_id: Some(account_id),
+            blob_id: MaybeReference::Value(blob_id),
+        }
+    }
+
+    pub fn with_account_id(mut self, account_id: Id) -> Self {
+        self.account_id = Some(account_id);
+        self
+    }
+
+    pub fn with_blob_id(mut self, blob_id: BlobId) -> Self {
+        self.blob_id = MaybeReference::Value(blob_id);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use store::BlobClass;
+    use utils::BlobHash;
+
+    use crate::{
+        error::{
+            method::MethodError,
+            set::{SetError, SetErrorType},
+        },
+        parser::{json::Parser, Error, JsonObjectParser},
+        request::reference::MaybeReference,
+        types::{blob::BlobId, id::Id},
+    };
+
+    use super::{ValidateSieveScriptRequest, ValidateSieveScriptResponse};
+
+    #[test]
+    fn blob_id_reference() {
+        let blob_id = BlobId::new(
+            BlobHash::default(),
+            BlobClass::Reserved {
+                account_id: 3,
+                expires: 0,
+            },
+        );
+        let json = format!(
+            r#"{{"accountId": "{}", "blobId": "{blob_id}"}}"#,
+            Id::new(7)
+        );
+        let request = ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())).unwrap();
+        assert_eq!(request.blob_id, MaybeReference::Value(blob_id));
+
+        // Back-references are left for reference resolution
+        let json = format!(
+            r##"{{"accountId": "{}", "#blobId": {{"resultOf": "c0", "name": "Blob/upload", "path": "/created/s0/id"}}}}"##,
+            Id::new(7)
+        );
+        let request = ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())).unwrap();
+        match &request.blob_id {
+            MaybeReference::Reference(reference) => {
+                assert_eq!(reference.result_of, "c0");
+                assert_eq!(reference.name.to_string(), "Blob/upload");
+            }
+            MaybeReference::Value(_) => panic!("expected a reference"),
+        }
+    }
+
+    #[test]
+    fn account_id_range() {
+        for (account_id, expected) in [
+            (Id::new(0).to_string(), Some(Id::new(0))),
+            (Id::new(7).to_string(), Some(Id::new(7))),
+            (
+                Id::new(u32::MAX as u64 - 1).to_string(),
+                Some(Id::new(u32::MAX as u64 - 1)),
+            ),
+            // Sentinel
+            (Id::new(u32::MAX as u64).to_string(), None),
+            // Out of range
+            (Id::new(u32::MAX as u64 + 1).to_string(), None),
+            (Id::from_parts(1, 7).to_string(), None),
+            (Id::default().to_string(), None),
+            // Overflowing
+            ("p333333333333p333333333333".to_string(), None),
+            (String::new(), None),
+        ] {
+            let json = format!(r#"{{"accountId": "{account_id}"}}"#);
+            match (
+                ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())),
+                expected,
+            ) {
+                (Ok(request), Some(expected)) => assert_eq!(request.account_id, Some(expected)),
+                (Err(Error::Method(MethodError::InvalidArguments(description))), None) => {
+                    assert!(description.contains("accountId"), "{description}");
+                }
+                (result, _) => panic!("unexpected result for {json}: {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_arguments() {
+        let account_id = Id::new(7);
+        let blob_id = BlobId::default();
+        for (json, property) in [
+            (
+                format!(
+                    r#"{{"accountId": "{account_id}", "accountId": "{}"}}"#,
+                    Id::new(8)
+                ),
+                "accountId",
+            ),
+            (
+                format!(
+                    r#"{{"blobId": "{blob_id}", "accountId": "{account_id}", "blobId": "{blob_id}"}}"#
+                ),
+                "blobId",
+            ),
+        ] {
+            match ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())) {
+                Err(Error::Method(MethodError::InvalidArguments(description))) => {
+                    assert!(description.contains(property), "{description}");
+                }
+                result => panic!("unexpected result for {json}: {result:?}"),
+            }
+        }
+
+        // Unknown keys may still repeat
+        let json = format!(r#"{{"accountId": "{account_id}", "other": 1, "other": 2}}"#);
+        assert_eq!(
+            ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes()))
+                .unwrap()
+                .account_id,
+            Some(account_id)
+        );
+    }
+
+    #[test]
+    fn strict_mode() {
+        let account_id = Id::new(7);
+        let json = format!(
+            r#"{{"accountId": "{account_id}", "blobID": "{}", "other": {{"a": [1]}}}}"#,
+            BlobId::default()
+        );
+
+        // Typos are ignored by default
+        let request = ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())).unwrap();
+        assert_eq!(request.account_id, Some(account_id));
+        assert_eq!(request.blob_id, MaybeReference::Value(BlobId::default()));
+
+        match ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes()).with_strict(true))
+        {
+            Err(Error::Method(MethodError::InvalidArguments(description))) => {
+                assert_eq!(description, r#"Unknown argument "blobID"."#);
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        let json = format!(r#"{{"accountId": "{account_id}"}}"#);
+        assert!(ValidateSieveScriptRequest::parse(
+            &mut Parser::new(json.as_bytes()).with_strict(true)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn implied_account_id() {
+        let account_id = Id::new(7);
+        for (json, expected) in [
+            (
+                format!(r#"{{"accountId": "{account_id}"}}"#),
+                Some(account_id),
+            ),
+            (r#"{"other": null}"#.to_string(), None),
+        ] {
+            assert_eq!(
+                ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes()))
+                    .unwrap()
+                    .account_id,
+                expected
+            );
+        }
+
+        // An explicit null is not the same as omitting the argument
+        assert!(matches!(
+            ValidateSieveScriptRequest::parse(&mut Parser::new(br#"{"accountId": null}"#)),
+            Err(Error::Method(MethodError::InvalidArguments(_)))
+        ));
+    }
+
+    #[test]
+    fn error_offset() {
+        // Offsets are in bytes, so multibyte characters count more than once
+        let json = r#"{"other": "čaj ☕", "blobId": "n0t-b@se32"}"#;
+        match ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())) {
+            Err(Error::Method(MethodError::InvalidArguments(description))) => {
+                assert_eq!(
+                    description,
+                    format!(
+                        "Invalid value \"n0t-b@se32\" at position {}.",
+                        json.find("n0t").unwrap()
+                    )
+                );
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        // Truncated input reports the end of the input
+        let json = r#"{"blobId": "abc"#;
+        match ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())) {
+            Err(Error::Request(err)) => {
+                assert!(
+                    err.detail
+                        .ends_with(&format!("at position {}.", json.len())),
+                    "{}",
+                    err.detail
+                );
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn warnings() {
+        for warnings in [
+            None,
+            Some(vec![SetError::new(SetErrorType::InvalidScript)
+                .with_description("Variable \"unused\" is never read.")]),
+        ] {
+            let response = serde_json::to_value(ValidateSieveScriptResponse {
+                account_id: Id::new(7),
+                error: None,
+                warnings: warnings.clone(),
+            })
+            .unwrap();
+
+            assert_eq!(response.get("warnings").is_some(), warnings.is_some());
+            if warnings.is_some() {
+                assert_eq!(response["warnings"][0]["type"], "invalidScript");
+                assert_eq!(
+                    response["warnings"][0]["description"],
+                    "Variable \"unused\" is never read."
+                );
+            }
+            assert!(response["error"].is_null());
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let blob_id = BlobId::new(
+            BlobHash::default(),
+            BlobClass::Reserved {
+                account_id: 3,
+                expires: 0,
+            },
+        );
+        for request in [
+            ValidateSieveScriptRequest::new(Id::new(3), blob_id.clone()),
+            ValidateSieveScriptRequest {
+                account_id: None,
+                blob_id: MaybeReference::Value(blob_id.clone()),
+            },
+        ] {
+            let json = serde_json::to_string(&request).unwrap();
+            let parsed = serde_json::from_str::<ValidateSieveScriptRequest>(&json).unwrap();
+            assert_eq!(parsed.account_id, request.account_id);
+            assert_eq!(parsed.blob_id, request.blob_id);
+
+            // Both parsers agree
+            let parsed =
+                ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())).unwrap();
+            assert_eq!(parsed.account_id, request.account_id);
+            assert_eq!(parsed.blob_id, request.blob_id);
+        }
+        assert_eq!(
+            serde_json::to_value(ValidateSieveScriptRequest::new(Id::new(3), blob_id.clone()))
+                .unwrap(),
+            serde_json::json!({"accountId": Id::new(3).to_string(), "blobId": blob_id.to_string()})
+        );
+    }
+}
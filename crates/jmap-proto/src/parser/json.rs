@@ -23,7 +23,9 @@
 
 use std::{fmt::Display, iter::Peekable, slice::Iter};
 
-use crate::{error::method::MethodError, request::method::MethodObject};
+use crate::{
+    error::method::MethodError, request::method::MethodObject, types::blob::MAX_BLOB_ID_LEN,
+};
 
 use super::{Error, Ignore, JsonObjectParser, Token};
 
@@ -39,6 +41,9 @@ pub struct Parser<'x> {
     pub depth_array: u32,
     pub depth_dict: u32,
     pub is_eof: bool,
+    pub max_depth: u32,
+    pub max_blob_id_len: usize,
+    pub strict: bool,
     pub ctx: MethodObject,
 }
 
@@ -53,20 +58,51 @@ impl<'x> Parser<'x> {
             is_eof: false,
             depth_array: 0,
             depth_dict: 0,
+            max_depth: MAX_NESTED_LEVELS,
+            max_blob_id_len: MAX_BLOB_ID_LEN,
+            strict: false,
             ctx: MethodObject::Core,
         }
     }
 
+    /// Sets the maximum number of nested arrays and objects, past which
+    /// parsing fails with [`Error::TooDeep`].
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum length in bytes of a blob id, longer values are
+    /// rejected as soon as the limit is reached.
+    pub fn with_max_blob_id_len(mut self, max_blob_id_len: usize) -> Self {
+        self.max_blob_id_len = max_blob_id_len;
+        self
+    }
+
+    /// Enables strict mode, where objects reject the properties they don't
+    /// recognize instead of ignoring them, see
+    /// [`Parser::skip_unknown_property`].
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Byte offset of the last byte read, or the input length once the
+    /// input is exhausted.
+    pub fn offset(&self) -> usize {
+        self.pos.saturating_sub(1).min(self.bytes.len())
+    }
+
     pub fn error(&self, message: &str) -> Error {
-        format!("{message} at position {}.", self.pos).into()
+        format!("{message} at position {}.", self.offset()).into()
     }
 
     pub fn error_unterminated(&self) -> Error {
-        format!("Unterminated string at position {pos}.", pos = self.pos).into()
+        format!("Unterminated string at position {}.", self.offset()).into()
     }
 
     pub fn error_utf8(&self) -> Error {
-        format!("Invalid UTF-8 sequence at position {pos}.", pos = self.pos).into()
+        format!("Invalid UTF-8 sequence at position {}.", self.offset()).into()
     }
 
     pub fn error_value(&mut self) -> Error {
@@ -74,7 +110,7 @@ impl<'x> Parser<'x> {
             Error::Method(MethodError::InvalidArguments(format!(
                 "Invalid value {:?} at position {}.",
                 String::from_utf8_lossy(self.bytes[self.pos_marker..self.pos - 1].as_ref()),
-                self.pos
+                self.pos_marker
             )))
         } else {
             self.error_unterminated()
@@ -151,11 +187,11 @@ impl<'x> Parser<'x> {
                     return Ok(Token::Colon);
                 }
                 b'[' => {
-                    if self.depth_array + self.depth_dict < MAX_NESTED_LEVELS {
+                    if self.depth_array + self.depth_dict < self.max_depth {
                         self.depth_array += 1;
                         return Ok(Token::ArrayStart);
                     } else {
-                        return Err(self.error("Too many nested objects"));
+                        return Err(Error::TooDeep(self.offset()));
                     }
                 }
                 b']' => {
@@ -167,11 +203,11 @@ impl<'x> Parser<'x> {
                     };
                 }
                 b'{' => {
-                    if self.depth_array + self.depth_dict < MAX_NESTED_LEVELS {
+                    if self.depth_array + self.depth_dict < self.max_depth {
                         self.depth_dict += 1;
                         return Ok(Token::DictStart);
                     } else {
-                        return Err(self.error("Too many nested objects"));
+                        return Err(Error::TooDeep(self.offset()));
                     }
                 }
                 b'}' => {
@@ -298,6 +334,81 @@ impl<'x> Parser<'x> {
         }
     }
 
+    /// Advances through the current dictionary until `name` is found,
+    /// returning `true` with the parser positioned to read its value, or
+    /// `false` once the dictionary ends. Values of other keys, including
+    /// nested objects and arrays, are skipped. Keys are compared as they
+    /// appear in the input, without unescaping.
+    pub fn seek_dict_key(&mut self, name: &str) -> super::Result<bool> {
+        let depth_array = self.depth_array;
+        let depth_dict = self.depth_dict;
+
+        loop {
+            match self.next_token::<Ignore>()? {
+                Token::String(_) => {
+                    let is_match =
+                        self.bytes.get(self.pos_marker..self.pos - 1) == Some(name.as_bytes());
+                    self.next_token::<Ignore>()?.assert(Token::Colon)?;
+                    if is_match {
+                        return Ok(true);
+                    }
+                    self.skip_token(depth_array, depth_dict)?;
+                }
+                Token::Comma => (),
+                Token::DictEnd => return Ok(false),
+                token => {
+                    return Err(self.error(&format!("Expected object property, found {}", token)))
+                }
+            }
+        }
+    }
+
+    /// Skips the value of a property the object being parsed doesn't
+    /// recognize or, in strict mode, fails with an `invalidArguments` error
+    /// naming the property. Must be called right after the property was
+    /// returned by [`Parser::next_dict_key`].
+    pub fn skip_unknown_property(&mut self) -> super::Result<()> {
+        if self.strict {
+            let key = self
+                .bytes
+                .get(self.pos_marker..self.pos)
+                .unwrap_or_default();
+            let key = &key[..key.iter().rposition(|&ch| ch == b'"').unwrap_or(0)];
+
+            Err(Error::Method(MethodError::InvalidArguments(format!(
+                "Unknown argument {:?}.",
+                String::from_utf8_lossy(key)
+            ))))
+        } else {
+            self.skip_token(self.depth_array, self.depth_dict)
+        }
+    }
+
+    /// Reads the next value as a string, decoding its escapes, failing with
+    /// an `invalidArguments` error when it is longer than `max_len`
+    /// characters or contains a character rejected by `allow`.
+    pub fn next_bounded_string(
+        &mut self,
+        max_len: usize,
+        allow: impl Fn(char) -> bool,
+    ) -> super::Result<String> {
+        let value = self.next_token::<String>()?.unwrap_string("")?;
+
+        if value.chars().nth(max_len).is_some() {
+            Err(Error::Method(MethodError::InvalidArguments(format!(
+                "String at position {} exceeds the maximum length of {max_len} characters.",
+                self.pos_marker
+            ))))
+        } else if let Some(ch) = value.chars().find(|&ch| !allow(ch)) {
+            Err(Error::Method(MethodError::InvalidArguments(format!(
+                "Invalid character {ch:?} in string at position {}.",
+                self.pos_marker
+            ))))
+        } else {
+            Ok(value)
+        }
+    }
+
     pub fn skip_token(
         &mut self,
         start_depth_array: u32,
@@ -315,7 +426,10 @@ impl<'x> Parser<'x> {
 #[cfg(test)]
 mod tests {
 
-    use crate::parser::Token;
+    use crate::{
+        error::method::MethodError,
+        parser::{Error, Ignore, JsonObjectParser, Token},
+    };
 
     use super::Parser;
 
@@ -402,4 +516,93 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn seek_dict_key() {
+        let json =
+            br#"{"a": {"target": 1, "b": [{"c": {}}, [2]]}, "d": "x", "target": ["y", "z"]}"#;
+        let mut parser = Parser::new(json);
+        parser
+            .next_token::<Ignore>()
+            .unwrap()
+            .assert(Token::DictStart)
+            .unwrap();
+
+        // Keys inside nested objects are not matched
+        assert!(parser.seek_dict_key("target").unwrap());
+        assert_eq!(
+            Option::<Vec<String>>::parse(&mut parser).unwrap(),
+            Some(vec!["y".to_string(), "z".to_string()])
+        );
+        assert!(!parser.seek_dict_key("target").unwrap());
+        assert_eq!((parser.depth_array, parser.depth_dict), (0, 0));
+
+        let mut parser = Parser::new(br#"{"a": {"b": 1}, "c": [true]}"#);
+        parser
+            .next_token::<Ignore>()
+            .unwrap()
+            .assert(Token::DictStart)
+            .unwrap();
+        assert!(!parser.seek_dict_key("missing").unwrap());
+        assert_eq!((parser.depth_array, parser.depth_dict), (0, 0));
+    }
+
+    #[test]
+    fn bounded_string() {
+        let json = r#"["token-1", "caf\u00e9\/x", "abcdefghi", "to$ken", "a\u0000b", 12]"#;
+        let is_token = |ch: char| ch.is_alphanumeric() || matches!(ch, '-' | '/');
+        let mut parser = Parser::new(json.as_bytes());
+        parser
+            .next_token::<Ignore>()
+            .unwrap()
+            .assert(Token::ArrayStart)
+            .unwrap();
+
+        assert_eq!(parser.next_bounded_string(8, is_token).unwrap(), "token-1");
+        parser
+            .next_token::<Ignore>()
+            .unwrap()
+            .assert(Token::Comma)
+            .unwrap();
+        assert_eq!(parser.next_bounded_string(6, is_token).unwrap(), "café/x");
+
+        for expected in [
+            "exceeds the maximum length of 8 characters",
+            "Invalid character '$'",
+            "Invalid character '\\0'",
+            "expected 'string'",
+        ] {
+            parser
+                .next_token::<Ignore>()
+                .unwrap()
+                .assert(Token::Comma)
+                .unwrap();
+            match parser.next_bounded_string(8, is_token) {
+                Err(Error::Method(MethodError::InvalidArguments(description))) => {
+                    assert!(description.contains(expected), "{description}");
+                }
+                result => panic!("unexpected result {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn max_depth() {
+        let json = format!("{}{}", "[".repeat(1000), "]".repeat(1000));
+
+        let mut parser = Parser::new(json.as_bytes());
+        assert!(matches!(parser.skip_token(0, 0), Err(Error::TooDeep(16))));
+
+        let mut parser = Parser::new(json.as_bytes()).with_max_depth(1000);
+        parser.skip_token(0, 0).unwrap();
+        assert_eq!(parser.depth_array, 0);
+
+        let json = format!(r#"{{"a": {}}}"#, "{\"b\": ".repeat(100));
+        assert!(matches!(
+            Parser::new(json.as_bytes())
+                .with_max_depth(64)
+                .skip_token(0, 0),
+            Err(Error::TooDeep(_))
+        ));
+    }
 }
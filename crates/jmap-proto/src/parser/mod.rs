@@ -60,6 +60,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     Request(RequestError),
     Method(MethodError),
+    /// Nesting exceeded the parser's maximum depth at the given byte offset.
+    TooDeep(usize),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
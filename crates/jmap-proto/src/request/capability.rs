@@ -361,7 +361,7 @@ impl JsonObjectParser for Capability {
                 _ => Err(parser.error_capability()),
             },
             Err(Error::Method(_)) => Err(parser.error_capability()),
-            Err(err @ Error::Request(_)) => Err(err),
+            Err(err) => Err(err),
         }
     }
 }
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use serde_json::value::RawValue;
+use serde_json::{value::RawValue, Value};
 use std::fmt::Write;
 
 use crate::parser::{json::Parser, JsonObjectParser, Token};
@@ -41,15 +41,48 @@ impl JsonObjectParser for Echo {
         let mut value = String::new();
 
         while {
+            // Strings and floats are re-encoded as JSON to be returned verbatim
             let _ = match parser.next_token::<String>()? {
-                Token::String(string) => write!(value, "{string:?}"),
+                Token::String(string) => write!(value, "{}", Value::String(string)),
+                Token::Float(float) => write!(value, "{}", Value::from(float)),
                 token => write!(value, "{token}"),
             };
             start_depth_array != parser.depth_array || start_depth_dict != parser.depth_dict
         } {}
 
         Ok(Echo {
-            payload: RawValue::from_string(value).unwrap(),
+            payload: RawValue::from_string(value)
+                .map_err(|_| parser.error("Invalid echo arguments"))?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::parser::{json::Parser, JsonObjectParser};
+
+    use super::Echo;
+
+    #[test]
+    fn echo_round_trip() {
+        for input in [
+            r#"{}"#,
+            r#"{"hello": true, "high": 5, "low": -1.5, "big": 3.0, "none": null}"#,
+            r#"{"a": {"b": [1, {"c": []}, "d"], "e": {}}, "f": [[["g"]]]}"#,
+            r#"{"quote": "say \"hi\"\n", "unicode": "caf\u00e9 \u200b \u0001", "slash": "a\\b"}"#,
+        ] {
+            let echo = Echo::parse(&mut Parser::new(input.as_bytes())).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Value>(echo.payload.get()).unwrap(),
+                serde_json::from_str::<Value>(input).unwrap(),
+                "failed for {input}"
+            );
+            assert_eq!(
+                serde_json::to_value(&echo).unwrap()["payload"],
+                serde_json::from_str::<Value>(input).unwrap()
+            );
+        }
+    }
+}
@@ -243,13 +243,22 @@ impl From<Error> for RequestError {
         match value {
             Error::Request(err) => err,
             Error::Method(err) => RequestError::not_request(err.to_string()),
+            Error::TooDeep(offset) => {
+                RequestError::not_json(&format!("Too many nested objects at position {offset}."))
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::request::Request;
+    use std::collections::HashMap;
+
+    use crate::{
+        error::request::RequestLimitError,
+        request::Request,
+        types::{any_id::AnyId, id::Id},
+    };
 
     const TEST: &str = r#"
     {
@@ -369,4 +378,34 @@ mod tests {
         println!("{:?}", Request::parse(TEST.as_bytes(), 10, 10240));
         println!("{:?}", Request::parse(TEST2.as_bytes(), 10, 10240));
     }
+
+    #[test]
+    fn parse_created_ids() {
+        let request = Request::parse(TEST.as_bytes(), 10, 10240).unwrap();
+        let created_ids = request.created_ids.unwrap();
+        assert_eq!(
+            created_ids,
+            HashMap::from([
+                ("c1".to_string(), AnyId::Id(Id::from_bytes(b"m1").unwrap())),
+                ("c2".to_string(), AnyId::Id(Id::from_bytes(b"m2").unwrap())),
+            ])
+        );
+        assert_eq!(
+            created_ids.get("c2").and_then(AnyId::as_id),
+            Id::from_bytes(b"m2").as_ref()
+        );
+        assert!(Request::parse(TEST2.as_bytes(), 10, 10240)
+            .unwrap()
+            .created_ids
+            .is_none());
+    }
+
+    #[test]
+    fn parse_size_limit() {
+        assert!(Request::parse(TEST.as_bytes(), 10, TEST.len()).is_ok());
+        assert!(matches!(
+            Request::parse(TEST.as_bytes(), 10, TEST.len() - 1),
+            Err(err) if matches!(err.limit, Some(RequestLimitError::SizeRequest))
+        ));
+    }
 }
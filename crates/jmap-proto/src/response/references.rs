@@ -36,6 +36,7 @@ use crate::{
     types::{
         any_id::AnyId,
         id::Id,
+        pointer::JSONPointer,
         property::Property,
         value::{MaybePatchValue, SetValue, Value},
     },
@@ -155,6 +156,25 @@ impl Response {
                     );
                 }
             }
+            RequestMethod::ValidateScript(request) => {
+                // Resolve blobId reference
+                if let MaybeReference::Reference(reference) = &request.blob_id {
+                    let mut ids = self
+                        .eval_result_references(reference)
+                        .unwrap_any_ids(reference)?
+                        .into_iter();
+                    match (ids.next(), ids.next()) {
+                        (Some(MaybeReference::Value(AnyId::Blob(blob_id))), None) => {
+                            request.blob_id = MaybeReference::Value(blob_id);
+                        }
+                        _ => {
+                            return Err(MethodError::InvalidResultReference(format!(
+                                "Result reference {reference} does not point to a single blobId."
+                            )));
+                        }
+                    }
+                }
+            }
             RequestMethod::UploadBlob(request) => {
                 let mut graph = HashMap::with_capacity(request.create.len());
                 for (create_id, object) in request.create.iter_mut() {
@@ -255,6 +275,41 @@ impl Response {
                             EvalResult::Failed
                         };
                     }
+                    ResponseMethod::UploadBlob(response) => {
+                        return match &rr.path {
+                            JSONPointer::Path(path) if path.len() == 3 => {
+                                match (&path[0], &path[1], &path[2]) {
+                                    (
+                                        JSONPointer::String(root),
+                                        JSONPointer::Wildcard,
+                                        JSONPointer::String(property),
+                                    ) if root == "created" && property == "id" => {
+                                        EvalResult::Values(
+                                            response
+                                                .created
+                                                .values()
+                                                .map(|object| Value::BlobId(object.id.clone()))
+                                                .collect(),
+                                        )
+                                    }
+                                    (
+                                        JSONPointer::String(root),
+                                        JSONPointer::String(creation_id),
+                                        JSONPointer::String(property),
+                                    ) if root == "created" && property == "id" => response
+                                        .created
+                                        .get(creation_id)
+                                        .map_or(EvalResult::Failed, |object| {
+                                            EvalResult::Values(vec![Value::BlobId(
+                                                object.id.clone(),
+                                            )])
+                                        }),
+                                    _ => EvalResult::Failed,
+                                }
+                            }
+                            _ => EvalResult::Failed,
+                        };
+                    }
                     _ => (),
                 }
             }
@@ -542,17 +597,78 @@ impl EvalResult {
 mod tests {
     use std::collections::HashMap;
 
+    use ahash::AHashMap;
+
     use crate::{
         error::method::MethodError,
-        request::{Request, RequestMethod},
+        method::{
+            upload::{BlobUploadResponse, BlobUploadResponseObject},
+            validate::ValidateSieveScriptRequest,
+        },
+        parser::{json::Parser, JsonObjectParser},
+        request::{
+            method::{MethodFunction, MethodName, MethodObject},
+            reference::MaybeReference,
+            Request, RequestMethod,
+        },
         response::Response,
         types::{
+            blob::BlobId,
             id::Id,
             property::Property,
             value::{SetValue, Value},
         },
     };
 
+    #[test]
+    fn validate_script_blob_reference() {
+        let mut response = Response::new(1234, HashMap::new(), 2);
+        let blob_id = BlobId::default();
+        response.push_response(
+            "c0".to_string(),
+            MethodName::new(MethodObject::Blob, MethodFunction::Upload),
+            BlobUploadResponse {
+                account_id: Id::new(1),
+                created: AHashMap::from_iter(["k1", "k2"].map(|creation_id| {
+                    (
+                        creation_id.to_string(),
+                        BlobUploadResponseObject {
+                            id: blob_id.clone(),
+                            type_: None,
+                            size: 0,
+                        },
+                    )
+                })),
+                ..Default::default()
+            },
+        );
+
+        for (path, expected) in [
+            ("/created/k1/id", Some(blob_id)),
+            ("/created/k3/id", None),
+            // Matches the blobIds of both uploads
+            ("/created/*/id", None),
+        ] {
+            let json = format!(
+                r##"{{"#blobId": {{"resultOf": "c0", "name": "Blob/upload", "path": "{path}"}}}}"##
+            );
+            let mut method = RequestMethod::ValidateScript(
+                ValidateSieveScriptRequest::parse(&mut Parser::new(json.as_bytes())).unwrap(),
+            );
+
+            match (response.resolve_references(&mut method), expected) {
+                (Ok(()), Some(expected)) => match method {
+                    RequestMethod::ValidateScript(request) => {
+                        assert_eq!(request.blob_id, MaybeReference::Value(expected))
+                    }
+                    _ => unreachable!(),
+                },
+                (Err(MethodError::InvalidResultReference(_)), None) => (),
+                (result, _) => panic!("Unexpected result for {path}: {result:?}"),
+            }
+        }
+    }
+
     #[test]
     fn eval_references() {
         let request = Request::parse(
@@ -35,7 +35,10 @@ use utils::{
     BlobHash,
 };
 
-use crate::parser::{base32::JsonBase32Reader, json::Parser, JsonObjectParser};
+use crate::{
+    error::method::MethodError,
+    parser::{base32::JsonBase32Reader, json::Parser, Error, JsonObjectParser},
+};
 
 const B_LINKED: u8 = 0x10;
 const B_RESERVED: u8 = 0x20;
@@ -54,13 +57,31 @@ pub struct BlobSection {
     pub encoding: u8,
 }
 
+/// Default limit for the length of a blob id in a request, see
+/// [`Parser::with_max_blob_id_len`].
+pub const MAX_BLOB_ID_LEN: usize = 4096;
+
 impl JsonObjectParser for BlobId {
     fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
     where
         Self: Sized,
     {
-        let mut it = JsonBase32Reader::new(parser);
-        BlobId::from_iter(&mut it).ok_or_else(|| it.error())
+        let start_pos = parser.pos;
+        let blob_id = BlobId::from_iter(&mut JsonBase32Reader::new(parser));
+
+        // Consume the rest of the value, rejecting oversized ones without
+        // reading them to the end
+        while !parser.is_eof {
+            if parser.pos - start_pos >= parser.max_blob_id_len {
+                return Err(Error::Method(MethodError::InvalidArguments(format!(
+                    "BlobId at position {start_pos} exceeds the maximum length of {} bytes.",
+                    parser.max_blob_id_len
+                ))));
+            }
+            parser.next_unescaped()?;
+        }
+
+        blob_id.ok_or_else(|| parser.error_value())
     }
 }
 
@@ -208,6 +229,16 @@ impl serde::Serialize for BlobId {
     }
 }
 
+impl<'de> serde::Deserialize<'de> for BlobId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        BlobId::from_base32(String::deserialize(deserializer)?)
+            .ok_or_else(|| serde::de::Error::custom("invalid JMAP BlobId"))
+    }
+}
+
 impl std::fmt::Display for BlobId {
     #[allow(clippy::unused_io_amount)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -228,3 +259,77 @@ impl DeserializeFrom for BlobId {
         BlobId::from_iter(bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use store::BlobClass;
+    use utils::BlobHash;
+
+    use crate::parser::{json::Parser, Error};
+
+    use super::BlobId;
+
+    #[test]
+    fn max_blob_id_len() {
+        let blob_id = BlobId::new(
+            BlobHash::default(),
+            BlobClass::Reserved {
+                account_id: 3,
+                expires: 0,
+            },
+        );
+        let json = format!(r#""{blob_id}""#);
+        assert_eq!(
+            Parser::new(json.as_bytes())
+                .next_token::<BlobId>()
+                .unwrap()
+                .unwrap_string("blobId")
+                .unwrap(),
+            blob_id
+        );
+
+        // Oversized values are rejected without reading them entirely
+        let json = format!(r#""{blob_id}{}""#, "a".repeat(10 * 1024 * 1024));
+        let mut parser = Parser::new(json.as_bytes());
+        assert!(matches!(
+            parser.next_token::<BlobId>(),
+            Err(Error::Method(_))
+        ));
+        assert!(parser.pos <= super::MAX_BLOB_ID_LEN + 1);
+
+        // Junk is rejected early as well
+        let json = format!(r#""!{}""#, "a".repeat(10 * 1024 * 1024));
+        let mut parser = Parser::new(json.as_bytes()).with_max_blob_id_len(64);
+        assert!(parser.next_token::<BlobId>().is_err());
+        assert!(parser.pos <= 65);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let blob_id = BlobId::new(
+            BlobHash::default(),
+            BlobClass::Reserved {
+                account_id: 3,
+                expires: 0,
+            },
+        );
+        let json = serde_json::to_string(&blob_id).unwrap();
+
+        assert_eq!(serde_json::from_str::<BlobId>(&json).unwrap(), blob_id);
+        assert_eq!(
+            serde_json::from_reader::<_, BlobId>(json.as_bytes()).unwrap(),
+            blob_id
+        );
+        assert_eq!(
+            serde_json::from_value::<BlobId>(serde_json::to_value(&blob_id).unwrap()).unwrap(),
+            blob_id
+        );
+
+        // Escaped strings can't be borrowed from the input
+        let text = blob_id.to_string();
+        let escaped = format!(r#""\u{:04x}{}""#, text.as_bytes()[0], &text[1..]);
+        assert_eq!(serde_json::from_str::<BlobId>(&escaped).unwrap(), blob_id);
+
+        assert!(serde_json::from_str::<BlobId>(r#""!invalid""#).is_err());
+    }
+}
@@ -65,12 +65,14 @@ impl Id {
         Self { id }
     }
 
+    /// Decodes an id, returning `None` on invalid characters or if the
+    /// value does not fit in 64 bits.
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        let mut id = 0;
+        let mut id: u64 = 0;
 
         for &ch in bytes {
             let i = BASE32_INVERSE[ch as usize];
-            if i != u8::MAX {
+            if i != u8::MAX && id.leading_zeros() >= 5 {
                 id = (id << 5) | i as u64;
             } else {
                 return None;
@@ -278,4 +280,19 @@ mod tests {
             .unwrap_string("")
             .unwrap();
     }
+
+    #[test]
+    fn id_from_bytes() {
+        for number in [0, 1, 1000, u32::MAX as u64, u64::MAX - 1, u64::MAX] {
+            let id = Id::from(number);
+            assert_eq!(Id::from_bytes(id.to_string().as_bytes()), Some(id));
+        }
+
+        // Leading zeros are not an overflow
+        assert_eq!(Id::from_bytes(b"aaaaaaaaaaaaaaaaab"), Some(Id::new(1)));
+
+        for invalid in [&b"p333333333333p333333333333"[..], b"zzzzzzzzzzzzz", b"a-b"] {
+            assert_eq!(Id::from_bytes(invalid), None, "{invalid:?}");
+        }
+    }
 }
@@ -300,9 +300,15 @@ impl JMAP {
                 self.email_search_snippet(req, access_token).await?.into()
             }
             RequestMethod::ValidateScript(req) => {
-                access_token.assert_is_member(req.account_id)?;
-
-                self.sieve_script_validate(req, access_token).await?.into()
+                // Clients may omit accountId to validate for their primary account
+                let account_id = req
+                    .account_id
+                    .unwrap_or_else(|| access_token.primary_id().into());
+                access_token.assert_is_member(account_id)?;
+
+                self.sieve_script_validate(req, account_id, access_token)
+                    .await?
+                    .into()
             }
             RequestMethod::CopyBlob(req) => {
                 access_token.assert_is_member(req.account_id)?;
@@ -27,6 +27,7 @@ use jmap_proto::{
         set::{SetError, SetErrorType},
     },
     method::validate::{ValidateSieveScriptRequest, ValidateSieveScriptResponse},
+    types::id::Id,
 };
 
 use crate::{auth::AccessToken, JMAP};
@@ -35,12 +36,19 @@ impl JMAP {
     pub async fn sieve_script_validate(
         &self,
         request: ValidateSieveScriptRequest,
+        account_id: Id,
         access_token: &AccessToken,
     ) -> Result<ValidateSieveScriptResponse, MethodError> {
+        let blob_id = request.blob_id.try_unwrap().ok_or_else(|| {
+            MethodError::InvalidResultReference("Unresolved blobId reference.".into())
+        })?;
+
+        // Scripts may be read from any blob the caller has access to,
+        // including those in shared accounts
         Ok(ValidateSieveScriptResponse {
-            account_id: request.account_id,
+            account_id,
             error: match self
-                .blob_download(&request.blob_id, access_token)
+                .blob_download(&blob_id, access_token)
                 .await?
                 .map(|bytes| self.core.sieve.untrusted_compiler.compile(&bytes))
             {
@@ -50,6 +58,7 @@ impl JMAP {
                     .into(),
                 None => SetError::new(SetErrorType::BlobNotFound).into(),
             },
+            warnings: None,
         })
     }
 }
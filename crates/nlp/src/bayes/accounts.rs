@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::hash::Hash;
+
+use ahash::AHashMap;
+use utils::suffixlist::PublicSuffix;
+
+use super::{BayesClassifier, BayesModel};
+
+#[derive(Debug, Default)]
+pub struct AccountModel {
+    pub classifier: BayesClassifier,
+    pub model: BayesModel,
+}
+
+/// Per-account models keyed by account id (such as a JMAP `Id`), falling
+/// back to a shared global model for accounts without enough training.
+#[derive(Debug)]
+pub struct AccountModels<K> {
+    pub global: AccountModel,
+    accounts: AHashMap<K, AccountModel>,
+}
+
+impl<K: Hash + Eq + Clone> AccountModels<K> {
+    pub fn new(global: AccountModel) -> Self {
+        AccountModels {
+            global,
+            accounts: AHashMap::new(),
+        }
+    }
+
+    pub fn get(&self, account_id: &K) -> Option<&AccountModel> {
+        self.accounts.get(account_id)
+    }
+
+    /// Returns the account's model, creating an empty one with the global
+    /// classifier settings on first use.
+    pub fn get_or_default(&mut self, account_id: &K) -> &mut AccountModel {
+        if !self.accounts.contains_key(account_id) {
+            self.accounts.insert(
+                account_id.clone(),
+                AccountModel {
+                    classifier: self.global.classifier.clone(),
+                    model: BayesModel::default(),
+                },
+            );
+        }
+        self.accounts.get_mut(account_id).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Classifies the text with the account's model when it has reached the
+    /// classifier's `min_learns`, or with the global model otherwise.
+    pub fn classify_text(
+        &self,
+        account_id: &K,
+        text: &str,
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> Option<f64> {
+        let account = self
+            .accounts
+            .get(account_id)
+            .filter(|account| {
+                let min_learns = account.classifier.min_learns;
+                account.model.spam_learns >= min_learns.max(1)
+                    && account.model.ham_learns >= min_learns.max(1)
+            })
+            .unwrap_or(&self.global);
+
+        account
+            .classifier
+            .classify_text(&account.model, text, suffixes, window_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, BayesClassifier, BayesModel},
+        tokenizers::osb::OsbTokenizer,
+    };
+
+    use super::{AccountModel, AccountModels};
+
+    #[test]
+    fn account_models() {
+        let suffixes = PublicSuffix::default();
+        let corpus = [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ];
+        let mut model = BayesModel::default();
+        for (text, is_spam) in corpus {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+        let mut models = AccountModels::<u64>::new(AccountModel {
+            classifier: BayesClassifier {
+                min_tokens: 1,
+                min_learns: 1,
+                ..BayesClassifier::new()
+            },
+            model,
+        });
+
+        // The first account learns the opposite labels of the global model
+        for (account_id, invert) in [(1, true), (2, false)] {
+            let account = models.get_or_default(&account_id);
+            for (text, is_spam) in corpus {
+                account.model.train(
+                    OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                    is_spam != invert,
+                );
+            }
+        }
+        assert_eq!(models.len(), 2);
+
+        assert_eq!(
+            models.classify_text(&99, "meeting", &suffixes, 1),
+            Some(0.125)
+        );
+        assert_eq!(
+            models.classify_text(&1, "meeting", &suffixes, 1),
+            Some(0.875)
+        );
+        assert_eq!(
+            models.classify_text(&2, "meeting", &suffixes, 1),
+            Some(0.125)
+        );
+        assert!(models.get(&99).is_none());
+
+        // Untrained accounts fall back to the global model
+        assert_eq!(models.get_or_default(&3).model.spam_learns, 0);
+        assert_eq!(
+            models.classify_text(&3, "meeting", &suffixes, 1),
+            Some(0.125)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::thread;
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::{Gram, OsbToken, OsbTokenizer};
+
+use super::tokenize::BayesTokenizer;
+
+/// Tokenizes `messages` into OSB grams on up to `threads` scoped threads,
+/// returning the grams of each message in input order. Messages are split
+/// into contiguous chunks and each message gets its own tokenizer, so no
+/// buffers are shared between threads.
+pub fn tokenize_batch<'x, T>(
+    messages: impl IntoIterator<Item = &'x str>,
+    suffixes: &PublicSuffix,
+    window_size: usize,
+    threads: usize,
+) -> Vec<Vec<OsbToken<T>>>
+where
+    T: for<'y> From<Gram<'y>> + Send + 'static,
+{
+    let messages = messages.into_iter().collect::<Vec<_>>();
+    let threads = threads.clamp(1, messages.len().max(1));
+    let tokenize = |text: &str| {
+        OsbTokenizer::new(BayesTokenizer::new(text, suffixes), window_size).collect::<Vec<_>>()
+    };
+
+    if threads == 1 {
+        return messages.into_iter().map(tokenize).collect();
+    }
+
+    thread::scope(|scope| {
+        messages
+            .chunks(messages.len().div_ceil(threads))
+            .map(|chunk| {
+                scope.spawn(move || chunk.iter().map(|text| tokenize(text)).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+            .collect::<Vec<Vec<_>>>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, TokenHash},
+        tokenizers::osb::OsbTokenizer,
+    };
+
+    use super::tokenize_batch;
+
+    #[test]
+    fn batch_matches_sequential() {
+        let suffixes = PublicSuffix::default();
+        let messages = ["cheap pills", "team meeting notes", "", "pills"];
+        let sequential = messages
+            .iter()
+            .map(|text| {
+                OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, &suffixes), 2)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sequential.iter().map(Vec::len).collect::<Vec<_>>(),
+            [3, 5, 0, 1]
+        );
+
+        for threads in [0, 1, 2, 3, 4, 64] {
+            assert_eq!(
+                tokenize_batch::<TokenHash>(messages.iter().copied(), &suffixes, 2, threads),
+                sequential,
+                "threads: {threads}"
+            );
+        }
+        assert!(tokenize_batch::<TokenHash>([], &suffixes, 2, 4).is_empty());
+    }
+}
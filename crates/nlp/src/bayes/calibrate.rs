@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use super::BayesClassifier;
+
+const MAX_ITERATIONS: usize = 100;
+const MIN_STEP: f64 = 1e-10;
+const SIGMA: f64 = 1e-12;
+
+/// Sigmoid mapping `p = 1 / (1 + exp(a * score + b))` fitted on labeled
+/// raw classifier scores.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlattCalibration {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl PlattCalibration {
+    pub fn apply(&self, score: f64) -> f64 {
+        let f_ab = score * self.a + self.b;
+        if f_ab >= 0.0 {
+            (-f_ab).exp() / (1.0 + (-f_ab).exp())
+        } else {
+            1.0 / (1.0 + f_ab.exp())
+        }
+    }
+
+    /// Fits the sigmoid parameters using Newton's method with backtracking
+    /// line search, as described by Lin, Lin and Weng in "A note on Platt's
+    /// probabilistic outputs for support vector machines".
+    pub fn fit(labeled_scores: &[(f64, bool)]) -> Option<Self> {
+        if labeled_scores.is_empty() {
+            return None;
+        }
+
+        let prior1 = labeled_scores
+            .iter()
+            .filter(|(_, is_spam)| *is_spam)
+            .count() as f64;
+        let prior0 = labeled_scores.len() as f64 - prior1;
+
+        // Regularized targets to avoid overfitting
+        let hi_target = (prior1 + 1.0) / (prior1 + 2.0);
+        let lo_target = 1.0 / (prior0 + 2.0);
+        let targets = labeled_scores
+            .iter()
+            .map(|(score, is_spam)| (*score, if *is_spam { hi_target } else { lo_target }))
+            .collect::<Vec<_>>();
+
+        let mut a = 0.0;
+        let mut b = ((prior0 + 1.0) / (prior1 + 1.0)).ln();
+        let mut fval = objective(&targets, a, b);
+
+        for _ in 0..MAX_ITERATIONS {
+            let mut h11 = SIGMA;
+            let mut h22 = SIGMA;
+            let mut h21 = 0.0;
+            let mut g1 = 0.0;
+            let mut g2 = 0.0;
+
+            for &(score, target) in &targets {
+                let f_ab = score * a + b;
+                let (p, q) = if f_ab >= 0.0 {
+                    let e = (-f_ab).exp();
+                    (e / (1.0 + e), 1.0 / (1.0 + e))
+                } else {
+                    let e = f_ab.exp();
+                    (1.0 / (1.0 + e), e / (1.0 + e))
+                };
+                let d2 = p * q;
+                h11 += score * score * d2;
+                h22 += d2;
+                h21 += score * d2;
+                let d1 = target - p;
+                g1 += score * d1;
+                g2 += d1;
+            }
+
+            if g1.abs() < 1e-5 && g2.abs() < 1e-5 {
+                break;
+            }
+
+            let det = h11 * h22 - h21 * h21;
+            let da = -(h22 * g1 - h21 * g2) / det;
+            let db = -(-h21 * g1 + h11 * g2) / det;
+            let gd = g1 * da + g2 * db;
+
+            let mut step = 1.0;
+            while step >= MIN_STEP {
+                let new_a = a + step * da;
+                let new_b = b + step * db;
+                let new_f = objective(&targets, new_a, new_b);
+                if new_f < fval + 0.0001 * step * gd {
+                    a = new_a;
+                    b = new_b;
+                    fval = new_f;
+                    break;
+                }
+                step /= 2.0;
+            }
+
+            if step < MIN_STEP {
+                break;
+            }
+        }
+
+        if a.is_finite() && b.is_finite() {
+            Some(PlattCalibration { a, b })
+        } else {
+            None
+        }
+    }
+}
+
+impl BayesClassifier {
+    /// Fits a Platt calibration on `(raw score, is_spam)` pairs so that
+    /// `classify` returns calibrated probabilities. Calibration is removed
+    /// if no usable fit can be obtained.
+    pub fn calibrate(&mut self, labeled_scores: &[(f64, bool)]) {
+        self.calibration = PlattCalibration::fit(labeled_scores);
+    }
+}
+
+fn objective(targets: &[(f64, f64)], a: f64, b: f64) -> f64 {
+    targets
+        .iter()
+        .map(|&(score, target)| {
+            let f_ab = score * a + b;
+            if f_ab >= 0.0 {
+                target * f_ab + (1.0 + (-f_ab).exp()).ln()
+            } else {
+                (target - 1.0) * f_ab + (1.0 + f_ab.exp()).ln()
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bayes::BayesClassifier;
+
+    #[test]
+    fn platt_calibration() {
+        // Overconfident raw scores: 0.9 is only spam in 7 out of 10 messages
+        let mut labeled_scores = Vec::new();
+        for (score, spam) in [(0.1, 1), (0.3, 3), (0.7, 5), (0.9, 7)] {
+            labeled_scores.extend((0..10).map(|i| (score, i < spam)));
+        }
+
+        let mut classifier = BayesClassifier::new();
+        classifier.calibrate(&labeled_scores);
+        let calibration = classifier.calibration.unwrap();
+
+        let mut last = 0.0;
+        for score in (0..=100).map(|i| i as f64 / 100.0) {
+            let prob = calibration.apply(score);
+            assert!(prob > 0.0 && prob < 1.0, "{score} -> {prob}");
+            assert!(prob >= last, "{score} -> {prob} < {last}");
+            last = prob;
+        }
+        assert!(calibration.apply(0.9) < 0.8);
+        assert!(calibration.apply(0.1) > 0.05);
+
+        classifier.calibrate(&[]);
+        assert_eq!(classifier.calibration, None);
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::{Gram, OsbToken, OsbTokenizer};
+
+use super::{tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash, WeightStore};
+
+/// Classifies with a word-level OSB model and a character n-gram model,
+/// relying on the latter as word-level features become novel. Heavily
+/// obfuscated messages (`buycheappills`) produce words the word model has
+/// never seen, while most of their character n-grams are still familiar.
+#[derive(Debug, Clone)]
+pub struct HybridClassifier {
+    pub classifier: BayesClassifier,
+    pub char_classifier: BayesClassifier,
+    pub char_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HybridScore {
+    pub word: Option<f64>,
+    pub chars: Option<f64>,
+    /// Fraction of word grams unknown to the word model.
+    pub novelty: f64,
+    /// `word * (1 - novelty) + chars * novelty`, or whichever score is
+    /// available when the other model could not classify.
+    pub score: Option<f64>,
+}
+
+/// Character n-grams of the lowercased text, with runs of whitespace
+/// collapsed into a single space. Texts shorter than `size` characters
+/// produce a single gram.
+pub fn char_ngrams(text: &str, size: usize) -> impl Iterator<Item = OsbToken<TokenHash>> {
+    let mut chars = Vec::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        if !chars.is_empty() {
+            chars.push(' ');
+        }
+        chars.extend(word.chars().flat_map(char::to_lowercase));
+    }
+
+    let size = size.max(1);
+    let mut gram = String::with_capacity(size * 4);
+    chars
+        .windows(size.min(chars.len()).max(1))
+        .map(|window| {
+            gram.clear();
+            gram.extend(window);
+            OsbToken {
+                inner: TokenHash::from(Gram::Uni { t1: &gram }),
+                idx: 0,
+            }
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+impl BayesModel {
+    pub fn train_chars(&mut self, text: &str, size: usize, is_spam: bool) {
+        self.train(char_ngrams(text, size), is_spam);
+    }
+}
+
+impl HybridClassifier {
+    pub fn new(classifier: BayesClassifier, char_size: usize) -> Self {
+        HybridClassifier {
+            char_classifier: classifier.clone(),
+            classifier,
+            char_size,
+        }
+    }
+
+    pub fn classify_text(
+        &self,
+        word_model: &BayesModel,
+        char_model: &impl WeightStore,
+        text: &str,
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> HybridScore {
+        let tokens =
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, suffixes), window_size)
+                .collect::<Vec<_>>();
+        let novelty = word_model.novelty(tokens.iter().cloned());
+        let word = self.classifier.classify(
+            tokens.into_iter().filter_map(|t| {
+                OsbToken {
+                    inner: word_model.weights(&t.inner)?,
+                    idx: t.idx,
+                }
+                .into()
+            }),
+            word_model.ham_learns(),
+            word_model.spam_learns(),
+        );
+
+        let chars = self.char_classifier.classify(
+            char_ngrams(text, self.char_size).filter_map(|t| {
+                OsbToken {
+                    inner: char_model.weights(&t.inner)?,
+                    idx: t.idx,
+                }
+                .into()
+            }),
+            char_model.ham_learns(),
+            char_model.spam_learns(),
+        );
+
+        HybridScore {
+            word,
+            chars,
+            novelty,
+            score: match (word, chars) {
+                (Some(word), Some(chars)) => Some(word * (1.0 - novelty) + chars * novelty),
+                (word, chars) => word.or(chars),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, BayesClassifier, BayesModel},
+        tokenizers::osb::OsbTokenizer,
+    };
+
+    use super::{char_ngrams, HybridClassifier};
+
+    #[test]
+    fn char_fallback() {
+        let suffixes = PublicSuffix::default();
+        let mut word_model = BayesModel::default();
+        let mut char_model = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            word_model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+            char_model.train_chars(text, 5, is_spam);
+        }
+        let classifier = HybridClassifier::new(
+            BayesClassifier {
+                min_tokens: 1,
+                min_learns: 1,
+                ..BayesClassifier::new()
+            },
+            5,
+        );
+
+        assert_eq!(char_ngrams("Ab  C", 3).count(), 2);
+        assert_eq!(char_ngrams("ab", 3).count(), 1);
+        assert_eq!(char_ngrams("", 3).count(), 0);
+
+        // The word model has never seen "xpill", while the char model knows
+        // the "pills" gram
+        let result = classifier.classify_text(&word_model, &char_model, "xpills", &suffixes, 1);
+        assert_eq!(result.word, None);
+        assert_eq!(result.chars, Some(0.875));
+        assert_eq!(result.novelty, 1.0);
+        assert_eq!(result.score, Some(0.875));
+
+        // Familiar text relies only on the word model
+        let result = classifier.classify_text(&word_model, &char_model, "meeting", &suffixes, 1);
+        assert_eq!(result.novelty, 0.0);
+        assert_eq!(result.word, Some(0.125));
+        assert!(
+            (result.chars.unwrap() - 0.030050).abs() < 1e-6,
+            "{result:?}"
+        );
+        assert_eq!(result.score, Some(0.125));
+
+        // Half of the word grams are novel, so both scores weigh the same
+        let result =
+            classifier.classify_text(&word_model, &char_model, "meeting xpills", &suffixes, 1);
+        assert_eq!(result.novelty, 0.5);
+        assert_eq!(result.word, Some(0.125));
+        assert!(
+            (result.chars.unwrap() - 0.179571).abs() < 1e-6,
+            "{result:?}"
+        );
+        assert!(
+            (result.score.unwrap() - 0.152286).abs() < 1e-6,
+            "{result:?}"
+        );
+    }
+}
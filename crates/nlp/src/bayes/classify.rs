@@ -21,18 +21,67 @@
  * for more details.
 */
 
-use crate::tokenizers::osb::OsbToken;
+use utils::suffixlist::PublicSuffix;
 
-use super::{BayesClassifier, Weights};
+use crate::tokenizers::osb::{OsbToken, OsbTokenizer};
+
+use super::{
+    frequency::DocumentFrequencyCounter, tokenize::BayesTokenizer, BayesClassifier, BayesModel,
+    TokenHash, WeightStore, Weights,
+};
 
 // Position 0 represents Unigram weights
 const FEATURE_WEIGHT: [f64; 8] = [1.0, 3125.0, 256.0, 27.0, 1.0, 0.0, 0.0, 0.0];
 
+// Reference feature count used when length normalization is enabled
+pub const LENGTH_NORM_TOKENS: u32 = 16;
+
 // Credits: ported from RSpamd
 impl BayesClassifier {
     pub fn classify<T>(&self, tokens: T, ham_learns: u32, spam_learns: u32) -> Option<f64>
     where
         T: Iterator<Item = OsbToken<Weights>>,
+    {
+        self.classify_weighted(tokens.map(|t| (t, 1.0)), ham_learns, spam_learns)
+    }
+
+    /// Classifies weighting each gram by its inverse document frequency.
+    ///
+    /// Each gram's log-probabilities are multiplied by `idf_i * n / sum(idf)`,
+    /// where `n` is the number of grams that passed the `min_token_hits` and
+    /// `min_prob_strength` filters. The weights average to one, so the
+    /// chi-square combination still sees `n` degrees of freedom, but grams
+    /// present in most documents contribute less evidence than distinctive
+    /// ones.
+    pub fn classify_tfidf<T>(
+        &self,
+        tokens: T,
+        df: &DocumentFrequencyCounter,
+        ham_learns: u32,
+        spam_learns: u32,
+    ) -> Option<f64>
+    where
+        T: Iterator<Item = OsbToken<(TokenHash, Weights)>>,
+    {
+        self.classify_weighted(
+            tokens.map(|t| {
+                let (hash, weights) = t.inner;
+                (
+                    OsbToken {
+                        inner: weights,
+                        idx: t.idx,
+                    },
+                    df.idf(&hash),
+                )
+            }),
+            ham_learns,
+            spam_learns,
+        )
+    }
+
+    fn classify_weighted<T>(&self, tokens: T, ham_learns: u32, spam_learns: u32) -> Option<f64>
+    where
+        T: Iterator<Item = (OsbToken<Weights>, f64)>,
     {
         if self.min_learns > 0 && (spam_learns < self.min_learns || ham_learns < self.min_learns) {
             return None;
@@ -41,8 +90,9 @@ impl BayesClassifier {
         let mut processed_tokens = 0;
         let mut total_spam_prob = 0.0;
         let mut total_ham_prob = 0.0;
+        let mut total_weight = 0.0;
 
-        for token in tokens {
+        for (token, weight) in tokens {
             let weights = token.inner;
             let total_count = weights.spam + weights.ham;
 
@@ -61,8 +111,9 @@ impl BayesClassifier {
                     || (bayes_spam_prob < 0.5 && bayes_spam_prob > 0.5 - self.min_prob_strength))
                 {
                     let bayes_ham_prob = prob_combine(ham_prob, total_count, w, 0.5);
-                    total_spam_prob += bayes_spam_prob.ln();
-                    total_ham_prob += bayes_ham_prob.ln();
+                    total_spam_prob += weight * bayes_spam_prob.ln();
+                    total_ham_prob += weight * bayes_ham_prob.ln();
+                    total_weight += weight;
                     processed_tokens += 1;
                 }
             }
@@ -72,6 +123,19 @@ impl BayesClassifier {
             return None;
         }
 
+        if total_weight > 0.0 && total_weight != processed_tokens as f64 {
+            let scale = processed_tokens as f64 / total_weight;
+            total_spam_prob *= scale;
+            total_ham_prob *= scale;
+        }
+
+        if self.length_normalize {
+            let scale = LENGTH_NORM_TOKENS as f64 / processed_tokens as f64;
+            total_spam_prob *= scale;
+            total_ham_prob *= scale;
+            processed_tokens = LENGTH_NORM_TOKENS;
+        }
+
         let (h, s) = if total_spam_prob > -300.0 && total_ham_prob > -300.0 {
             /* Fisher value is low enough to apply inv_chi_square */
             (
@@ -109,11 +173,92 @@ impl BayesClassifier {
         };
 
         if processed_tokens > 0 && (final_prob - 0.5).abs() > 0.05 {
-            Some(final_prob)
+            Some(
+                self.calibration
+                    .map_or(final_prob, |calibration| calibration.apply(final_prob)),
+            )
         } else {
             None
         }
     }
+
+    pub fn classify_text(
+        &self,
+        model: &impl WeightStore,
+        text: &str,
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> Option<f64> {
+        self.classify(
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, suffixes), window_size)
+                .filter_map(|t| {
+                    OsbToken {
+                        inner: model.weights(&t.inner)?,
+                        idx: t.idx,
+                    }
+                    .into()
+                }),
+            model.ham_learns(),
+            model.spam_learns(),
+        )
+    }
+
+    /// Classifies a batch of messages, returning the scores in input order.
+    /// Messages are scored in parallel when the `rayon` feature is enabled.
+    pub fn classify_many(
+        &self,
+        model: &BayesModel,
+        messages: &[&str],
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> Vec<Option<f64>> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            messages
+                .par_iter()
+                .map(|text| self.classify_text(model, text, suffixes, window_size))
+                .collect()
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            messages
+                .iter()
+                .map(|text| self.classify_text(model, text, suffixes, window_size))
+                .collect()
+        }
+    }
+
+    /// Returns a self-contained scoring function over a snapshot of the
+    /// classifier and `model`, so callers such as rule engines can score
+    /// messages without holding a reference to the mutable model. Messages
+    /// that cannot be classified score 0.5.
+    pub fn scorer(
+        &self,
+        model: &BayesModel,
+    ) -> impl Fn(&[OsbToken<TokenHash>]) -> f64 + Send + Sync + 'static {
+        let classifier = self.clone();
+        let weights = model.weights.clone();
+        let (ham_learns, spam_learns) = (model.ham_learns, model.spam_learns);
+
+        move |tokens| {
+            classifier
+                .classify(
+                    tokens.iter().filter_map(|t| {
+                        OsbToken {
+                            inner: *weights.get(&t.inner)?,
+                            idx: t.idx,
+                        }
+                        .into()
+                    }),
+                    ham_learns,
+                    spam_learns,
+                )
+                .unwrap_or(0.5)
+        }
+    }
 }
 
 /**
@@ -164,3 +309,203 @@ fn normalize_probability(x: f64, bias: f64) -> f64 {
 fn prob_combine(prob: f64, cnt: f64, weight: f64, assumed: f64) -> f64 {
     ((weight) * (assumed) + (cnt) * (prob)) / ((weight) + (cnt))
 }
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{
+            frequency::DocumentFrequencyCounter, tokenize::BayesTokenizer, BayesClassifier,
+            BayesModel, TokenHash, Weights,
+        },
+        tokenizers::osb::{Gram, OsbToken, OsbTokenizer},
+    };
+
+    #[test]
+    fn classify_many() {
+        let suffixes = PublicSuffix::default();
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let messages = ["pills", "meeting", "pills meeting", "unknown", ""];
+
+        // A single gram seen twice in one class scores
+        // (w * 0.5 + 2 * 1) / (w + 2) with w = 2 / 3, that is 7/8
+        let scores = classifier.classify_many(&model, &messages, &suffixes, 1);
+        assert_eq!(scores, vec![Some(0.875), Some(0.125), None, None, None]);
+        assert_eq!(
+            scores,
+            messages
+                .iter()
+                .map(|text| classifier.classify_text(&model, text, &suffixes, 1))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn length_normalize() {
+        let suffixes = PublicSuffix::default();
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+        let mut classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let short = "pills";
+        let long = "pills pills pills pills";
+
+        assert_eq!(
+            classifier.classify_text(&model, short, &suffixes, 1),
+            Some(0.875)
+        );
+        let long_score = classifier
+            .classify_text(&model, long, &suffixes, 1)
+            .unwrap();
+        assert!((long_score - 0.981822).abs() < 1e-6, "{long_score}");
+
+        // Both are rescaled to 16 grams of 7/8
+        classifier.length_normalize = true;
+        for text in [short, long] {
+            let score = classifier
+                .classify_text(&model, text, &suffixes, 1)
+                .unwrap();
+            assert!((score - 0.999839).abs() < 1e-6, "{score}");
+        }
+    }
+
+    #[test]
+    fn classify_tfidf() {
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let common = TokenHash::from(Gram::Uni { t1: "common" });
+        let rare = TokenHash::from(Gram::Uni { t1: "rare" });
+
+        // "common" appears in every document and leans spam, "rare" in a
+        // single one and leans ham, by the same amount
+        let mut df = DocumentFrequencyCounter::new();
+        df.documents = 100;
+        df.frequencies.insert(common, 100);
+        df.frequencies.insert(rare, 1);
+
+        let tokens = [
+            (common, Weights { spam: 9, ham: 1 }),
+            (rare, Weights { spam: 1, ham: 9 }),
+        ]
+        .into_iter()
+        .map(|inner| OsbToken { inner, idx: 0 })
+        .collect::<Vec<_>>();
+
+        // Without weighting both grams cancel out
+        assert_eq!(
+            classifier.classify(
+                tokens.iter().map(|t| OsbToken {
+                    inner: t.inner.1,
+                    idx: t.idx,
+                }),
+                10,
+                10,
+            ),
+            None
+        );
+
+        // idf(common) = 1 and idf(rare) = ln(101 / 2) + 1
+        let tfidf = classifier
+            .classify_tfidf(tokens.iter().cloned(), &df, 10, 10)
+            .unwrap();
+        assert!((tfidf - 0.190675).abs() < 1e-6, "{tfidf}");
+
+        // Uniform document frequencies leave the score unchanged
+        let mut uniform = DocumentFrequencyCounter::new();
+        uniform.documents = 100;
+        uniform.frequencies.insert(common, 10);
+        uniform.frequencies.insert(rare, 10);
+        assert_eq!(
+            classifier.classify_tfidf(tokens.iter().cloned(), &uniform, 10, 10),
+            None
+        );
+    }
+
+    #[test]
+    fn scorer() {
+        fn assert_send_sync<T: Send + Sync + 'static>(_: &T) {}
+
+        let suffixes = PublicSuffix::default();
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let scorer = classifier.scorer(&model);
+        assert_send_sync(&scorer);
+
+        let tokens = ["pills", "meeting", ""]
+            .iter()
+            .map(|text| {
+                OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, &suffixes), 1)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|tokens| scorer(tokens))
+                .collect::<Vec<_>>(),
+            vec![0.875, 0.125, 0.5]
+        );
+
+        // The scorer keeps its snapshot when the model is retrained, while
+        // "meeting" at 10 spam and 2 ham now falls within min_prob_strength
+        for _ in 0..10 {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new("meeting", &suffixes), 1),
+                true,
+            );
+        }
+        assert_eq!(scorer(&tokens[1]), 0.125);
+        assert_eq!(
+            classifier.classify_text(&model, "meeting", &suffixes, 1),
+            None
+        );
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::OsbTokenizer;
+
+use super::{tokenize::BayesTokenizer, TokenHash};
+
+/// Number of hash functions in a MinHash signature.
+pub const SIGNATURE_LEN: usize = 128;
+
+/// MinHash signature of the set of grams of a message. The fraction of
+/// positions where two signatures agree estimates the Jaccard similarity
+/// of their gram sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHash {
+    mins: [u64; SIGNATURE_LEN],
+}
+
+impl MinHash {
+    /// Returns `None` when there are no grams to sign.
+    pub fn new(grams: impl IntoIterator<Item = TokenHash>) -> Option<Self> {
+        let mut mins = [u64::MAX; SIGNATURE_LEN];
+        let mut has_grams = false;
+
+        for TokenHash { h1, h2 } in grams {
+            has_grams = true;
+            for (i, min) in mins.iter_mut().enumerate() {
+                // Kirsch-Mitzenmacher double hashing, mixed to break the
+                // linearity between hash functions
+                let hash = mix(h1.wrapping_add((i as u64).wrapping_mul(h2)));
+                if hash < *min {
+                    *min = hash;
+                }
+            }
+        }
+
+        has_grams.then_some(MinHash { mins })
+    }
+
+    pub fn from_text(text: &str, suffixes: &PublicSuffix, window_size: usize) -> Option<Self> {
+        Self::new(
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, suffixes), window_size)
+                .map(|t| t.inner),
+        )
+    }
+
+    /// Estimated Jaccard similarity between the gram sets of two messages.
+    pub fn similarity(&self, other: &MinHash) -> f64 {
+        self.mins
+            .iter()
+            .zip(other.mins.iter())
+            .filter(|(a, b)| a == b)
+            .count() as f64
+            / SIGNATURE_LEN as f64
+    }
+}
+
+/// Groups messages whose estimated gram similarity is at least `threshold`,
+/// returning the indexes of each group in ascending order. Every message
+/// belongs to exactly one group, so unrelated messages are returned as
+/// single-element groups.
+///
+/// Candidate pairs are found with LSH banding, using the longest band whose
+/// collision threshold `(1 / bands) ^ (1 / rows)` does not exceed
+/// `threshold`, and then confirmed against the full signatures.
+pub fn cluster(
+    messages: &[&str],
+    suffixes: &PublicSuffix,
+    window_size: usize,
+    threshold: f64,
+) -> Vec<Vec<usize>> {
+    let signatures = messages
+        .iter()
+        .map(|text| MinHash::from_text(text, suffixes, window_size))
+        .collect::<Vec<_>>();
+    let rows = band_rows(threshold);
+    let mut parents = (0..messages.len()).collect::<Vec<_>>();
+    let mut buckets: AHashMap<(usize, &[u64]), Vec<usize>> = AHashMap::new();
+
+    for (idx, signature) in signatures.iter().enumerate() {
+        let Some(signature) = signature else {
+            continue;
+        };
+        for (band, hashes) in signature.mins.chunks(rows).enumerate() {
+            let bucket = buckets.entry((band, hashes)).or_default();
+            for &other in bucket.iter() {
+                if find(&mut parents, idx) != find(&mut parents, other)
+                    && signatures[other]
+                        .as_ref()
+                        .is_some_and(|other| signature.similarity(other) >= threshold)
+                {
+                    let root = find(&mut parents, other);
+                    let idx_root = find(&mut parents, idx);
+                    parents[idx_root.max(root)] = idx_root.min(root);
+                }
+            }
+            bucket.push(idx);
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_ids = AHashMap::new();
+    for idx in 0..messages.len() {
+        let root = find(&mut parents, idx);
+        let group_id = *group_ids.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group_id].push(idx);
+    }
+
+    groups
+}
+
+fn band_rows(threshold: f64) -> usize {
+    let mut rows = 1;
+    while rows < SIGNATURE_LEN {
+        let next = rows * 2;
+        let bands = (SIGNATURE_LEN / next) as f64;
+        if (1.0 / bands).powf(1.0 / next as f64) > threshold {
+            break;
+        }
+        rows = next;
+    }
+    rows
+}
+
+fn find(parents: &mut [usize], mut idx: usize) -> usize {
+    while parents[idx] != idx {
+        parents[idx] = parents[parents[idx]];
+        idx = parents[idx];
+    }
+    idx
+}
+
+// splitmix64 finalizer
+fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use super::{band_rows, cluster, MinHash};
+
+    #[test]
+    fn cluster_similar_messages() {
+        let suffixes = PublicSuffix::default();
+        let messages = [
+            "Congratulations you have been selected to receive a free prize worth one thousand dollars claim your reward today by clicking the link below before the offer expires tonight",
+            "Please review the attached quarterly report before our meeting on Thursday and send me your comments on the budget section",
+            "Congratulations you have been selected to receive a free prize worth five thousand dollars claim your reward today by clicking the link below before the offer expires tonight",
+            "The team lunch has been moved to Friday at noon at the usual place near the office",
+            "Congratulations you have been selected to receive a free prize worth one thousand dollars claim your reward now by clicking the link below before the offer expires tonight",
+            "",
+            "Buy cheap pills online with no prescription needed and fast worldwide shipping on every order",
+        ];
+
+        assert_eq!(
+            cluster(&messages, &suffixes, 5, 0.5),
+            vec![vec![0, 2, 4], vec![1], vec![3], vec![5], vec![6]]
+        );
+
+        // Near-duplicates share most of their signature, unrelated messages don't
+        let a = MinHash::from_text(messages[0], &suffixes, 5).unwrap();
+        let b = MinHash::from_text(messages[2], &suffixes, 5).unwrap();
+        let c = MinHash::from_text(messages[1], &suffixes, 5).unwrap();
+        assert_eq!(a.similarity(&a), 1.0);
+        assert!(a.similarity(&b) > 0.7, "{}", a.similarity(&b));
+        assert!(a.similarity(&c) < 0.1, "{}", a.similarity(&c));
+        assert!(MinHash::from_text("", &suffixes, 5).is_none());
+        assert_eq!(
+            cluster(&messages, &suffixes, 5, 1.0),
+            (0..messages.len()).map(|idx| vec![idx]).collect::<Vec<_>>()
+        );
+
+        assert_eq!(band_rows(0.5), 4);
+        assert_eq!(band_rows(0.8), 8);
+        assert_eq!(band_rows(0.0), 1);
+    }
+}
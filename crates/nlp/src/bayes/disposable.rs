@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashSet;
+use lazy_static::lazy_static;
+
+use crate::list::CustomList;
+
+/// Disposable/temporary email domains, either merged with or replacing the
+/// built-in list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisposableDomains {
+    domains: AHashSet<String>,
+    replace_builtin: bool,
+}
+
+impl DisposableDomains {
+    pub fn replace_builtin(mut self, replace_builtin: bool) -> Self {
+        self.replace_builtin = replace_builtin;
+        self
+    }
+
+    /// Returns `true` if the lowercased domain, or any of its parent
+    /// domains, is listed.
+    pub fn contains(&self, domain: &str) -> bool {
+        let mut domain = domain;
+        loop {
+            if self.domains.contains(domain)
+                || (!self.replace_builtin && DISPOSABLE_DOMAINS.contains(domain))
+            {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => domain = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl CustomList for DisposableDomains {
+    fn insert(&mut self, entry: String) {
+        self.domains.insert(entry);
+    }
+
+    fn len(&self) -> usize {
+        self.domains.len()
+    }
+}
+
+/// The disposable domains list shipped with the spam filter, a single
+/// `spam-disposable = {"domain", ...}` entry.
+const BUNDLED_LIST: &str =
+    include_str!("../../../../resources/config/spamfilter/maps/domains_disposable.list");
+
+lazy_static! {
+    pub static ref DISPOSABLE_DOMAINS: AHashSet<&'static str> =
+        BUNDLED_LIST.split('"').skip(1).step_by(2).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::list::CustomList;
+
+    use super::{DisposableDomains, DISPOSABLE_DOMAINS};
+
+    #[test]
+    fn bundled_list() {
+        assert_eq!(DISPOSABLE_DOMAINS.len(), 427);
+        for domain in ["0815.ru", "mailinator.com", "zoemail.net"] {
+            assert!(DISPOSABLE_DOMAINS.contains(domain), "missing {domain:?}");
+        }
+        assert!(!DISPOSABLE_DOMAINS
+            .iter()
+            .any(|domain| domain.contains(['"', ',', '{', '}', '\n', ' '])));
+    }
+
+    #[test]
+    fn subdomain_matching() {
+        let builtin = DisposableDomains::default();
+        for (domain, expected) in [
+            ("mailinator.com", true),
+            ("inbox.yopmail.com", true),
+            ("a.b.guerrillamail.com", true),
+            ("notyopmail.com", false),
+            ("mailinator.com.example.org", false),
+            ("com", false),
+        ] {
+            assert_eq!(builtin.contains(domain), expected, "failed for {domain:?}");
+        }
+
+        // Custom lists are merged with the built-in one unless replacing it
+        let merged = DisposableDomains::from_entries(&["Throwaway.test"]);
+        assert!(merged.contains("mx.throwaway.test"));
+        assert!(merged.contains("mailinator.com"));
+        let replaced = DisposableDomains::from_entries(&["throwaway.test"]).replace_builtin(true);
+        assert!(replaced.contains("throwaway.test"));
+        assert!(!replaced.contains("mailinator.com"));
+    }
+
+    #[test]
+    fn from_reader() {
+        let list = DisposableDomains::from_reader(
+            &b"# local list\n\n  Throwaway.test  \n#ignored.test\nburner.example\n"[..],
+        )
+        .unwrap()
+        .replace_builtin(true);
+        assert_eq!(list.len(), 2);
+        assert!(list.contains("throwaway.test"));
+        assert!(list.contains("burner.example"));
+        assert!(!list.contains("ignored.test"));
+
+        let empty = DisposableDomains::from_reader(&b"# nothing here\n"[..])
+            .unwrap()
+            .replace_builtin(true);
+        assert!(empty.is_empty());
+        assert!(!empty.contains("mailinator.com"));
+    }
+}
@@ -0,0 +1,486 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::ops::RangeInclusive;
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::{OsbToken, OsbTokenizer};
+
+use super::{tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash, WeightStore};
+
+/// Confusion-matrix style comparison of two classifiers, with `a` taken as
+/// the baseline and `b` as the shadow model.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AgreementReport {
+    pub total: usize,
+    pub both_spam: usize,
+    pub both_ham: usize,
+    pub a_spam_b_ham: usize,
+    pub a_ham_b_spam: usize,
+    /// Messages that either model could not classify
+    pub unscored: usize,
+}
+
+/// Classification quality over a labeled set, a score above 0.5 being
+/// considered spam.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub auc: f64,
+}
+
+impl BayesModel {
+    /// Returns the fraction of grams in the stream that the model has never
+    /// seen during training, or 0.0 for an empty stream.
+    pub fn novelty<T>(&self, tokens: T) -> f64
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        let mut total = 0;
+        let mut unseen = 0;
+
+        for token in tokens {
+            total += 1;
+            if self
+                .weights
+                .get(&token.inner)
+                .map_or(true, |w| w.spam == 0 && w.ham == 0)
+            {
+                unseen += 1;
+            }
+        }
+
+        if total > 0 {
+            unseen as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Classifies each message with both models and counts how often their
+/// verdicts agree, a score above `threshold` being considered spam.
+pub fn agreement(
+    a: (&BayesClassifier, &BayesModel),
+    b: (&BayesClassifier, &BayesModel),
+    messages: &[&str],
+    suffixes: &PublicSuffix,
+    window_size: usize,
+    threshold: f64,
+) -> AgreementReport {
+    let mut report = AgreementReport {
+        total: messages.len(),
+        ..Default::default()
+    };
+
+    for text in messages {
+        match (
+            a.0.classify_text(a.1, text, suffixes, window_size),
+            b.0.classify_text(b.1, text, suffixes, window_size),
+        ) {
+            (Some(a_score), Some(b_score)) => match (a_score > threshold, b_score > threshold) {
+                (true, true) => report.both_spam += 1,
+                (false, false) => report.both_ham += 1,
+                (true, false) => report.a_spam_b_ham += 1,
+                (false, true) => report.a_ham_b_spam += 1,
+            },
+            _ => report.unscored += 1,
+        }
+    }
+
+    report
+}
+
+impl BayesClassifier {
+    /// Computes the ROC curve over a labeled validation set as
+    /// `(threshold, fpr, tpr)` points, where a message is considered spam
+    /// when its score is greater than or equal to the threshold. Points are
+    /// ordered by decreasing threshold, starting at `(inf, 0, 0)` and ending
+    /// at `(1, 1)`. Messages that cannot be classified are scored as 0.5.
+    pub fn roc_curve(
+        &self,
+        model: &impl WeightStore,
+        labeled: &[(&str, bool)],
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> Vec<(f64, f64, f64)> {
+        roc_points(
+            labeled
+                .iter()
+                .map(|(text, is_spam)| {
+                    (
+                        self.classify_text(model, text, suffixes, window_size)
+                            .unwrap_or(0.5),
+                        *is_spam,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Picks the window size in `windows` that best separates `labeled`,
+    /// using two-fold cross-validation: messages are alternately assigned to
+    /// two folds and each fold is scored by a model trained on the other.
+    /// Candidates are ranked by F1, then by AUC, with ties going to the
+    /// smallest window. Returns `None` for an empty range.
+    pub fn tune_window(
+        &self,
+        labeled: &[(&str, bool)],
+        windows: RangeInclusive<usize>,
+        suffixes: &PublicSuffix,
+    ) -> Option<(usize, Metrics)> {
+        let mut best: Option<(usize, Metrics)> = None;
+
+        for window_size in windows.filter(|window_size| *window_size > 0) {
+            let mut scores = Vec::with_capacity(labeled.len());
+            for fold in 0..2 {
+                let mut model = BayesModel::default();
+                for (_, (text, is_spam)) in labeled
+                    .iter()
+                    .enumerate()
+                    .filter(|(pos, _)| pos % 2 != fold)
+                {
+                    model.train(
+                        OsbTokenizer::new(BayesTokenizer::new(text, suffixes), window_size),
+                        *is_spam,
+                    );
+                }
+                scores.extend(
+                    labeled
+                        .iter()
+                        .enumerate()
+                        .filter(|(pos, _)| pos % 2 == fold)
+                        .map(|(_, (text, is_spam))| {
+                            (
+                                self.classify_text(&model, text, suffixes, window_size)
+                                    .unwrap_or(0.5),
+                                *is_spam,
+                            )
+                        }),
+                );
+            }
+
+            let metrics = Metrics::from_scores(scores);
+            if best.map_or(true, |(_, best)| {
+                metrics.f1 > best.f1 || (metrics.f1 == best.f1 && metrics.auc > best.auc)
+            }) {
+                best = Some((window_size, metrics));
+            }
+        }
+
+        best
+    }
+}
+
+impl Metrics {
+    fn from_scores(scores: Vec<(f64, bool)>) -> Self {
+        let (mut tp, mut fp, mut fn_) = (0, 0, 0);
+        for (score, is_spam) in &scores {
+            match (*score > 0.5, *is_spam) {
+                (true, true) => tp += 1,
+                (true, false) => fp += 1,
+                (false, true) => fn_ += 1,
+                (false, false) => (),
+            }
+        }
+
+        let precision = tp as f64 / (tp + fp).max(1) as f64;
+        let recall = tp as f64 / (tp + fn_).max(1) as f64;
+        Metrics {
+            precision,
+            recall,
+            f1: if precision + recall > 0.0 {
+                2.0 * precision * recall / (precision + recall)
+            } else {
+                0.0
+            },
+            auc: roc_auc(&roc_points(scores)),
+        }
+    }
+}
+
+fn roc_points(mut scores: Vec<(f64, bool)>) -> Vec<(f64, f64, f64)> {
+    scores.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+
+    let positives = scores.iter().filter(|(_, is_spam)| *is_spam).count();
+    let negatives = scores.len() - positives;
+    let mut curve = vec![(f64::INFINITY, 0.0, 0.0)];
+    let mut tp = 0;
+    let mut fp = 0;
+
+    for (pos, (score, is_spam)) in scores.iter().enumerate() {
+        if *is_spam {
+            tp += 1;
+        } else {
+            fp += 1;
+        }
+
+        // Emit a single point for tied scores
+        if scores.get(pos + 1).map_or(true, |(next, _)| next != score) {
+            curve.push((
+                *score,
+                fp as f64 / negatives.max(1) as f64,
+                tp as f64 / positives.max(1) as f64,
+            ));
+        }
+    }
+
+    curve
+}
+
+/// Area under a ROC curve computed with the trapezoidal rule.
+pub fn roc_auc(curve: &[(f64, f64, f64)]) -> f64 {
+    curve
+        .windows(2)
+        .map(|w| (w[1].1 - w[0].1) * (w[1].2 + w[0].2) / 2.0)
+        .sum()
+}
+
+impl AgreementReport {
+    pub fn agreed(&self) -> usize {
+        self.both_spam + self.both_ham
+    }
+
+    pub fn disagreed(&self) -> usize {
+        self.a_spam_b_ham + self.a_ham_b_spam
+    }
+
+    /// Fraction of the scored messages on which both models agree.
+    pub fn agreement_rate(&self) -> f64 {
+        let scored = self.total - self.unscored;
+        if scored > 0 {
+            self.agreed() as f64 / scored as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{
+            eval::{agreement, roc_auc, AgreementReport, Metrics},
+            tokenize::BayesTokenizer,
+            BayesClassifier, BayesModel,
+        },
+        tokenizers::osb::OsbTokenizer,
+    };
+
+    #[test]
+    fn classifier_agreement() {
+        let suffixes = PublicSuffix::default();
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+
+        // The shadow model is trained with inverted labels
+        let mut model = BayesModel::default();
+        let mut inverted = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            for (model, is_spam) in [(&mut model, is_spam), (&mut inverted, !is_spam)] {
+                model.train(
+                    OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                    is_spam,
+                );
+            }
+        }
+        let messages = ["pills", "meeting", "pills meeting", ""];
+
+        let report = agreement(
+            (&classifier, &model),
+            (&classifier, &model),
+            &messages,
+            &suffixes,
+            1,
+            0.5,
+        );
+        assert_eq!(
+            report,
+            AgreementReport {
+                total: 4,
+                unscored: 2,
+                both_spam: 1,
+                both_ham: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(report.agreement_rate(), 1.0);
+
+        let report = agreement(
+            (&classifier, &model),
+            (&classifier, &inverted),
+            &messages,
+            &suffixes,
+            1,
+            0.5,
+        );
+        assert_eq!(
+            report,
+            AgreementReport {
+                total: 4,
+                unscored: 2,
+                a_spam_b_ham: 1,
+                a_ham_b_spam: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(report.agreement_rate(), 0.0);
+    }
+
+    #[test]
+    fn roc_curve() {
+        let suffixes = PublicSuffix::default();
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+
+        // Scored 0.875, 0.5 (unknown), 0.875 and 0.125
+        let labeled = [
+            ("pills", true),
+            ("unknown", true),
+            ("pills", false),
+            ("meeting", false),
+        ];
+        let curve = classifier.roc_curve(&model, &labeled, &suffixes, 1);
+        assert_eq!(
+            curve,
+            vec![
+                (f64::INFINITY, 0.0, 0.0),
+                (0.875, 0.5, 0.5),
+                (0.5, 0.5, 1.0),
+                (0.125, 1.0, 1.0),
+            ]
+        );
+        assert_eq!(roc_auc(&curve), 0.625);
+        assert_eq!(roc_auc(&[(f64::INFINITY, 0.0, 0.0), (0.5, 1.0, 1.0)]), 0.5);
+    }
+
+    #[test]
+    fn tune_window() {
+        let suffixes = PublicSuffix::default();
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+
+        // Both classes share their unigrams, only the bigram order differs,
+        // so a window of 1 cannot tell them apart
+        let labeled = [
+            ("cheap pills", true),
+            ("cheap pills", true),
+            ("cheap pills", true),
+            ("cheap pills", true),
+            ("pills cheap", false),
+            ("pills cheap", false),
+            ("pills cheap", false),
+            ("pills cheap", false),
+        ];
+        assert_eq!(
+            classifier.tune_window(&labeled, 1..=1, &suffixes),
+            Some((
+                1,
+                Metrics {
+                    precision: 0.0,
+                    recall: 0.0,
+                    f1: 0.0,
+                    auc: 0.5,
+                }
+            ))
+        );
+
+        // Windows 2 and 3 both separate the classes, the smallest wins
+        assert_eq!(
+            classifier.tune_window(&labeled, 1..=3, &suffixes),
+            Some((
+                2,
+                Metrics {
+                    precision: 1.0,
+                    recall: 1.0,
+                    f1: 1.0,
+                    auc: 1.0,
+                }
+            ))
+        );
+        assert_eq!(
+            classifier
+                .tune_window(&labeled, 3..=3, &suffixes)
+                .map(|(window_size, _)| window_size),
+            Some(3)
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 5..=1;
+        assert_eq!(classifier.tune_window(&labeled, empty, &suffixes), None);
+    }
+
+    #[test]
+    fn novelty() {
+        let suffixes = PublicSuffix::default();
+        let mut model = BayesModel::default();
+        model.train(
+            OsbTokenizer::new(BayesTokenizer::new("cheap pills", &suffixes), 2),
+            true,
+        );
+
+        // "cheap zebra" yields "cheap", "cheap zebra" and "zebra"
+        for (text, expected) in [
+            ("cheap pills", 0.0),
+            ("zebra quantum", 1.0),
+            ("cheap zebra", 2.0 / 3.0),
+            ("", 0.0),
+        ] {
+            assert_eq!(
+                model.novelty(OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 2)),
+                expected,
+                "failed for {text:?}"
+            );
+        }
+    }
+}
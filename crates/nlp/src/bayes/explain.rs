@@ -0,0 +1,295 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashSet;
+use serde::Serialize;
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::{Gram, OsbToken, OsbTokenizer};
+
+use super::{tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash};
+
+// Number of contributing grams included in an explanation
+pub const EXPLAIN_TOP_GRAMS: usize = 10;
+
+/// Describes how a message was scored, intended to be serialized to JSON
+/// for logging and debugging.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub window_size: usize,
+    pub length_normalize: bool,
+    pub calibrated: bool,
+    /// Number of grams produced by the tokenizer
+    pub features: usize,
+    /// Number of grams with at least `min_token_hits` training hits
+    pub known_features: usize,
+    /// Known grams with the strongest spam or ham probability
+    pub top_grams: Vec<GramContribution>,
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GramContribution {
+    pub gram: String,
+    pub spam: u32,
+    pub ham: u32,
+    pub spam_prob: f64,
+}
+
+impl BayesClassifier {
+    /// Classifies a message returning, along with the score, the settings
+    /// and the grams that contributed the most to it.
+    pub fn classify_text_explained(
+        &self,
+        model: &BayesModel,
+        text: &str,
+        suffixes: &PublicSuffix,
+        window_size: usize,
+    ) -> Explanation {
+        let mut tokenizer =
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, suffixes), window_size);
+        let mut features = 0;
+        let mut tokens = Vec::new();
+        let mut seen = AHashSet::new();
+        let mut top_grams = Vec::new();
+
+        while let Some(token) = tokenizer.next_gram(|gram| {
+            let text = match &gram {
+                Gram::Uni { t1 } => t1.to_string(),
+                Gram::Bi { t1, t2 } => format!("{t1} {t2}"),
+                Gram::Tri { t1, t2, t3 } => format!("{t1} {t2} {t3}"),
+            };
+            (text, TokenHash::from(gram))
+        }) {
+            features += 1;
+            let (gram, hash) = token.inner;
+            if let Some(weights) = model
+                .weights
+                .get(&hash)
+                .filter(|w| w.spam + w.ham >= self.min_token_hits)
+            {
+                tokens.push(OsbToken {
+                    inner: *weights,
+                    idx: token.idx,
+                });
+
+                if seen.insert(hash) {
+                    let spam_freq = weights.spam as f64 / f64::max(1.0, model.spam_learns as f64);
+                    let ham_freq = weights.ham as f64 / f64::max(1.0, model.ham_learns as f64);
+                    top_grams.push(GramContribution {
+                        gram,
+                        spam: weights.spam,
+                        ham: weights.ham,
+                        spam_prob: spam_freq / (spam_freq + ham_freq),
+                    });
+                }
+            }
+        }
+
+        top_grams.sort_unstable_by(|a, b| {
+            (b.spam_prob - 0.5)
+                .abs()
+                .total_cmp(&(a.spam_prob - 0.5).abs())
+                .then_with(|| (b.spam + b.ham).cmp(&(a.spam + a.ham)))
+                .then_with(|| a.gram.cmp(&b.gram))
+        });
+        top_grams.truncate(EXPLAIN_TOP_GRAMS);
+
+        Explanation {
+            window_size,
+            length_normalize: self.length_normalize,
+            calibrated: self.calibration.is_some(),
+            features,
+            known_features: tokens.len(),
+            top_grams,
+            score: self.classify(tokens.into_iter(), model.ham_learns, model.spam_learns),
+        }
+    }
+}
+
+/// The grams of a message that share the same head token, so that a match
+/// can be shown as the word and its skip partners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadGroup<T> {
+    pub head_pos: usize,
+    pub tokens: Vec<OsbToken<T>>,
+}
+
+/// Regroups tokens tagged with the position of their head token, as
+/// returned by [`OsbTokenizer::head_pos`], into one group per head token in
+/// source order. Tokens within a group are sorted by `idx`, so the unigram
+/// comes first followed by the bigrams from the nearest to the farthest
+/// partner and then the trigram. Any subset of the grams of a message can be
+/// regrouped, such as those that matched a model.
+pub fn group_by_head<T>(
+    tokens: impl IntoIterator<Item = (usize, OsbToken<T>)>,
+) -> Vec<HeadGroup<T>> {
+    let mut tokens = tokens.into_iter().collect::<Vec<_>>();
+    tokens.sort_by_key(|(head_pos, token)| (*head_pos, token.idx));
+
+    let mut groups: Vec<HeadGroup<T>> = Vec::new();
+    for (head_pos, token) in tokens {
+        match groups.last_mut() {
+            Some(group) if group.head_pos == head_pos => group.tokens.push(token),
+            _ => groups.push(HeadGroup {
+                head_pos,
+                tokens: vec![token],
+            }),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, BayesClassifier, BayesModel},
+        tokenizers::osb::OsbTokenizer,
+    };
+
+    use super::{group_by_head, Explanation, GramContribution};
+
+    #[test]
+    fn classify_text_explained() {
+        let suffixes = PublicSuffix::default();
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [
+            ("cheap pills", true),
+            ("cheap pills", true),
+            ("cheap meeting", false),
+            ("cheap meeting", false),
+        ] {
+            model.train(
+                OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1),
+                is_spam,
+            );
+        }
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+
+        // "today" is unknown and "cheap" is neutral, so the score combines
+        // 1/2 and 7/8 with two degrees of freedom
+        let explanation =
+            classifier.classify_text_explained(&model, "cheap pills today", &suffixes, 1);
+        let score = explanation.score.unwrap();
+        assert!((score - 0.781693).abs() < 1e-6, "{score}");
+        assert_eq!(
+            explanation,
+            Explanation {
+                window_size: 1,
+                length_normalize: false,
+                calibrated: false,
+                features: 3,
+                known_features: 2,
+                top_grams: vec![
+                    GramContribution {
+                        gram: "pill".to_string(),
+                        spam: 2,
+                        ham: 0,
+                        spam_prob: 1.0,
+                    },
+                    GramContribution {
+                        gram: "cheap".to_string(),
+                        spam: 2,
+                        ham: 2,
+                        spam_prob: 0.5,
+                    },
+                ],
+                score: Some(score),
+            }
+        );
+        assert_eq!(
+            explanation.score,
+            classifier.classify_text(&model, "cheap pills today", &suffixes, 1)
+        );
+
+        let json = serde_json::to_value(&explanation).unwrap();
+        assert_eq!(json["window_size"], 1);
+        assert_eq!(json["top_grams"][0]["gram"], "pill");
+    }
+
+    #[test]
+    fn group_by_head_token() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat";
+        let mut tokenizer =
+            OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5);
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next() {
+            tokens.push((tokenizer.head_pos().unwrap(), token));
+        }
+        tokens.reverse();
+
+        let groups = group_by_head(tokens.clone());
+        assert_eq!(groups.len(), 13);
+        assert_eq!(
+            groups.iter().map(|g| g.head_pos).collect::<Vec<_>>(),
+            (0..13).collect::<Vec<_>>()
+        );
+        let members = |pos: usize| {
+            groups[pos]
+                .tokens
+                .iter()
+                .map(|t| (t.inner.as_str(), t.idx))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            members(0),
+            [
+                ("The", 0),
+                ("The quick", 1),
+                ("The brown", 2),
+                ("The fox", 3),
+                ("The jumps", 4)
+            ]
+        );
+        assert_eq!(
+            members(7),
+            [
+                ("lazy", 0),
+                ("lazy dog", 1),
+                ("lazy and", 2),
+                ("lazy the", 3),
+                ("lazy lazy", 4)
+            ]
+        );
+        assert_eq!(members(11), [("lazy", 0), ("lazy cat", 1)]);
+        assert_eq!(members(12), [("cat", 0)]);
+
+        // Subsets keep only the heads with matching grams
+        let groups = group_by_head(tokens.into_iter().filter(|(_, t)| t.inner.contains("fox")));
+        assert_eq!(
+            groups
+                .iter()
+                .map(|g| (g.head_pos, g.tokens.len()))
+                .collect::<Vec<_>>(),
+            [(0, 1), (1, 1), (2, 1), (3, 5)]
+        );
+    }
+}
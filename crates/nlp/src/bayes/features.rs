@@ -0,0 +1,407 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::language::{detect::LanguageDetector, Language};
+
+use super::disposable::DisposableDomains;
+
+pub const LANG_MISMATCH_TOKEN: &str = "__LANG_MISMATCH__";
+pub const DISPOSABLE_DOMAIN_TOKEN: &str = "__DISPOSABLE_DOMAIN__";
+
+/// Emits the sender reputation tokens for a From/Return-Path domain: the
+/// registrable domain (`fromdomain:example.co.uk`) and its public suffix
+/// (`fromtld:co.uk`).
+pub fn sender_domain_tokens(domain: &str, suffixes: &PublicSuffix) -> Vec<Cow<'static, str>> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let mut tokens = Vec::with_capacity(2);
+
+    if let Some(sld) = registrable_domain(&domain, suffixes) {
+        let tld = sld.split_once('.').map_or(sld, |(_, tld)| tld);
+        tokens.push(format!("fromdomain:{sld}").into());
+        tokens.push(format!("fromtld:{tld}").into());
+    }
+
+    tokens
+}
+
+/// Returns the registrable domain (public suffix plus one label) of a
+/// lowercased domain name, or `None` for single-label names.
+pub fn registrable_domain<'x>(domain: &'x str, suffixes: &PublicSuffix) -> Option<&'x str> {
+    let mut seen_dot = false;
+    for (pos, ch) in domain.as_bytes().iter().enumerate().rev() {
+        if *ch == b'.' {
+            if seen_dot {
+                let maybe_domain = &domain[pos + 1..];
+                if !suffixes.contains(maybe_domain) {
+                    return Some(maybe_domain);
+                }
+            } else {
+                seen_dot = true;
+            }
+        }
+    }
+
+    if seen_dot && !domain.starts_with('.') {
+        Some(domain)
+    } else {
+        None
+    }
+}
+
+/// Attachment metadata used to derive classifier tokens.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentInfo<'x> {
+    pub filename: Option<&'x str>,
+    pub content_type: Option<&'x str>,
+    pub size: usize,
+}
+
+// Upper bounds, in bytes, of the attachment and image size buckets
+const SIZE_BUCKETS: [(usize, &str); 4] = [
+    (1024, "tiny"),
+    (100 * 1024, "small"),
+    (1024 * 1024, "medium"),
+    (10 * 1024 * 1024, "large"),
+];
+
+/// Emits the type signature tokens of an attachment: its file extension
+/// (`att:ext:exe`), its MIME type without parameters (`att:type:text/html`)
+/// and a size bucket (`att:size:small`).
+pub fn attachment_tokens(attachment: &AttachmentInfo<'_>) -> Vec<Cow<'static, str>> {
+    let mut tokens = Vec::with_capacity(3);
+
+    if let Some(ext) = attachment.filename.and_then(file_extension) {
+        tokens.push(format!("att:ext:{ext}").into());
+    }
+
+    if let Some(content_type) = attachment.content_type.and_then(|ct| {
+        let ct = ct.split_once(';').map_or(ct, |(ct, _)| ct).trim();
+        if ct.contains('/') && !ct.contains(char::is_whitespace) {
+            Some(ct.to_lowercase())
+        } else {
+            None
+        }
+    }) {
+        tokens.push(format!("att:type:{content_type}").into());
+    }
+
+    tokens.push(format!("att:size:{}", size_bucket(attachment.size)).into());
+
+    tokens
+}
+
+fn file_extension(filename: &str) -> Option<String> {
+    let filename = filename
+        .trim()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default();
+    let (name, ext) = filename.rsplit_once('.')?;
+
+    if !name.is_empty()
+        && !ext.is_empty()
+        && ext.len() <= 8
+        && ext.chars().all(|ch| ch.is_ascii_alphanumeric())
+    {
+        Some(ext.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+/// Emits the tokens of an inline image: a presence marker (`img:present`),
+/// each word of its alt text (`img:alt:cheap`) and, when the image bytes
+/// are available, its format, size bucket and content hash
+/// (`img:format:png`, `img:size:small`, `img:hash:...`).
+pub fn image_tokens(alt: Option<&str>, image: Option<&[u8]>) -> Vec<Cow<'static, str>> {
+    let mut tokens = vec![Cow::Borrowed("img:present")];
+
+    if let Some(alt) = alt {
+        for word in alt.split(|ch: char| !ch.is_alphanumeric()) {
+            if !word.is_empty() {
+                tokens.push(format!("img:alt:{}", word.to_lowercase()).into());
+            }
+        }
+    }
+
+    if let Some(image) = image {
+        let format = match image {
+            [0x89, b'P', b'N', b'G', ..] => "png",
+            [0xff, 0xd8, 0xff, ..] => "jpeg",
+            [b'G', b'I', b'F', b'8', ..] => "gif",
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+            [b'B', b'M', ..] => "bmp",
+            _ => "unknown",
+        };
+        tokens.push(format!("img:format:{format}").into());
+        tokens.push(format!("img:size:{}", size_bucket(image.len())).into());
+        tokens.push(format!("img:hash:{:016x}", xxhash_rust::xxh3::xxh3_64(image)).into());
+    }
+
+    tokens
+}
+
+fn size_bucket(size: usize) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(limit, _)| size < *limit)
+        .map_or("huge", |(_, bucket)| bucket)
+}
+
+/// Returns the language mismatch marker when the language detected in the
+/// text with at least `min_confidence` is not among the languages declared
+/// in a `Content-Language` value such as `en-US, fr`. Text that cannot be
+/// confidently detected, or declarations without a known language, never
+/// produce the marker.
+pub fn language_mismatch_token(
+    declared: &str,
+    text: &str,
+    min_confidence: f64,
+) -> Option<&'static str> {
+    let declared = declared
+        .split(',')
+        .filter_map(|code| Language::from_iso_639(&code.trim().to_lowercase()))
+        .collect::<Vec<_>>();
+    if declared.is_empty() {
+        return None;
+    }
+
+    match LanguageDetector::detect_single(text) {
+        Some((detected, confidence))
+            if confidence >= min_confidence && !declared.contains(&detected) =>
+        {
+            Some(LANG_MISMATCH_TOKEN)
+        }
+        _ => None,
+    }
+}
+
+/// Emits [`DISPOSABLE_DOMAIN_TOKEN`] when a sender or Reply-To domain
+/// belongs to a disposable/temporary email provider.
+pub fn disposable_domain_token(
+    domain: &str,
+    disposable: &DisposableDomains,
+) -> Option<&'static str> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    if disposable.contains(&domain) {
+        Some(DISPOSABLE_DOMAIN_TOKEN)
+    } else {
+        None
+    }
+}
+
+const DAYS_OF_WEEK: [&str; 7] = ["thu", "fri", "sat", "sun", "mon", "tue", "wed"];
+
+/// Emits the send time tokens (`hour:03`, `dow:sat`) for a message date,
+/// given as the sender's wall-clock time in seconds since the Unix epoch
+/// and its UTC offset in seconds (negative west of Greenwich). Both buckets
+/// are computed in UTC so that the same instant always maps to the same
+/// tokens regardless of the sender's timezone.
+pub fn send_time_tokens(local_timestamp: i64, utc_offset: i64) -> [String; 2] {
+    let timestamp = local_timestamp - utc_offset;
+    let days = timestamp.div_euclid(86400);
+    let hour = timestamp.rem_euclid(86400) / 3600;
+
+    [
+        format!("hour:{hour:02}"),
+        format!("dow:{}", DAYS_OF_WEEK[days.rem_euclid(7) as usize]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::bayes::disposable::DisposableDomains;
+
+    use super::{
+        attachment_tokens, disposable_domain_token, image_tokens, language_mismatch_token,
+        send_time_tokens, sender_domain_tokens, AttachmentInfo, DISPOSABLE_DOMAIN_TOKEN,
+        LANG_MISMATCH_TOKEN,
+    };
+
+    #[test]
+    fn sender_domain_features() {
+        let suffixes = PublicSuffix::from("com\nuk\nco.uk");
+
+        for (domain, expected) in [
+            (
+                "mail.example.co.uk",
+                vec!["fromdomain:example.co.uk", "fromtld:co.uk"],
+            ),
+            (
+                "example.co.uk",
+                vec!["fromdomain:example.co.uk", "fromtld:co.uk"],
+            ),
+            (
+                "Bounces.Example.COM.",
+                vec!["fromdomain:example.com", "fromtld:com"],
+            ),
+            ("localhost", vec![]),
+        ] {
+            assert_eq!(
+                sender_domain_tokens(domain, &suffixes),
+                expected,
+                "failed for {domain:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn attachment_features() {
+        for (filename, content_type, size, expected) in [
+            (
+                Some("invoice.EXE"),
+                Some("application/octet-stream"),
+                300 * 1024,
+                vec![
+                    "att:ext:exe",
+                    "att:type:application/octet-stream",
+                    "att:size:medium",
+                ],
+            ),
+            (
+                Some("C:\\Users\\invoice.pdf.html"),
+                Some("Text/HTML; charset=utf-8"),
+                2048,
+                vec!["att:ext:html", "att:type:text/html", "att:size:small"],
+            ),
+            (
+                Some(".profile"),
+                Some("text/plain"),
+                10,
+                vec!["att:type:text/plain", "att:size:tiny"],
+            ),
+            (
+                Some("report"),
+                Some("garbage"),
+                20 * 1024 * 1024,
+                vec!["att:size:huge"],
+            ),
+            (None, None, 0, vec!["att:size:tiny"]),
+        ] {
+            assert_eq!(
+                attachment_tokens(&AttachmentInfo {
+                    filename,
+                    content_type,
+                    size,
+                }),
+                expected,
+                "failed for {filename:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn image_features() {
+        assert_eq!(
+            image_tokens(Some("Cheap  Pills, 50% OFF!"), None),
+            vec![
+                "img:present",
+                "img:alt:cheap",
+                "img:alt:pills",
+                "img:alt:50",
+                "img:alt:off"
+            ]
+        );
+        assert_eq!(image_tokens(None, None), vec!["img:present"]);
+
+        let png = b"\x89PNG\r\n\x1a\n0000";
+        let tokens = image_tokens(None, Some(png));
+        assert_eq!(
+            &tokens[..3],
+            &["img:present", "img:format:png", "img:size:tiny"]
+        );
+        assert!(tokens[3].starts_with("img:hash:"));
+        assert_eq!(tokens, image_tokens(None, Some(png)));
+        assert_ne!(tokens[3], image_tokens(None, Some(b"GIF89a"))[3]);
+    }
+
+    #[test]
+    fn language_mismatch() {
+        let english = concat!(
+            "Please find attached the quarterly financial report for your review, ",
+            "and let me know if you have any questions about the figures."
+        );
+        let spanish = "El rápido zorro marrón salta sobre el perro perezoso junto al río";
+
+        for (declared, text, expected) in [
+            ("en", english, None),
+            ("EN-us, fr", english, None),
+            ("es", spanish, None),
+            ("de", english, Some(LANG_MISMATCH_TOKEN)),
+            ("en", spanish, Some(LANG_MISMATCH_TOKEN)),
+            ("xx, ", spanish, None),
+            ("", english, None),
+        ] {
+            assert_eq!(
+                language_mismatch_token(declared, text, 0.5),
+                expected,
+                "failed for {declared:?}"
+            );
+        }
+
+        // Low confidence detections are ignored
+        assert_eq!(language_mismatch_token("de", english, 1.1), None);
+    }
+
+    #[test]
+    fn disposable_domain_feature() {
+        let builtin = DisposableDomains::default();
+        for (domain, expected) in [
+            ("mailinator.com", Some(DISPOSABLE_DOMAIN_TOKEN)),
+            ("Inbox.YOPMAIL.com.", Some(DISPOSABLE_DOMAIN_TOKEN)),
+            (" example.com ", None),
+        ] {
+            assert_eq!(
+                disposable_domain_token(domain, &builtin),
+                expected,
+                "failed for {domain:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn send_time_features() {
+        for (local_timestamp, utc_offset, expected) in [
+            // Sat, 16 Mar 2024 03:15:00 +0000
+            (1710558900, 0, ["hour:03", "dow:sat"]),
+            // Fri, 15 Mar 2024 22:15:00 -0500
+            (1710540900, -5 * 3600, ["hour:03", "dow:sat"]),
+            // Tue, 19 Mar 2024 08:59:00 +0900
+            (1710838740, 9 * 3600, ["hour:23", "dow:mon"]),
+            // Wed, 31 Dec 1969 23:00:00 +0000
+            (-3600, 0, ["hour:23", "dow:wed"]),
+        ] {
+            assert_eq!(
+                send_time_tokens(local_timestamp, utc_offset),
+                expected,
+                "failed for {local_timestamp} {utc_offset}"
+            );
+        }
+    }
+}
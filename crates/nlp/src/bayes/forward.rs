@@ -0,0 +1,258 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+pub const FORWARDED_TOKEN: &str = "__FORWARDED__";
+
+const FORWARD_HEADERS: [&str; 7] = ["from", "sent", "date", "to", "cc", "subject", "reply-to"];
+
+/// Mail client that produced a forwarded message block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardStyle {
+    /// `---------- Forwarded message ---------`
+    Gmail,
+    /// `-----Original Message-----` or an underscore rule followed by the
+    /// original `From:` header.
+    Outlook,
+    /// `Begin forwarded message:`
+    AppleMail,
+}
+
+/// Message text split from the boilerplate added by forwarding it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ForwardedText<'x> {
+    /// The text with the forward separators and original headers removed,
+    /// keeping the forwarded content itself.
+    pub text: String,
+    /// The removed separator and header lines, in order of appearance.
+    pub boilerplate: Vec<&'x str>,
+    pub styles: Vec<ForwardStyle>,
+}
+
+/// Detects forwarded message blocks, moving their separator line and the
+/// original header lines that follow it out of the text so that they don't
+/// train as regular body content.
+pub fn split_forwarded(text: &str) -> ForwardedText<'_> {
+    let lines = text.split_inclusive('\n').collect::<Vec<_>>();
+    let mut result = ForwardedText {
+        text: String::with_capacity(text.len()),
+        ..Default::default()
+    };
+    let mut pos = 0;
+
+    while pos < lines.len() {
+        let line = lines[pos];
+        let Some(style) = forward_style(line.trim(), &lines[pos + 1..]) else {
+            result.text.push_str(line);
+            pos += 1;
+            continue;
+        };
+
+        result.styles.push(style);
+        result.boilerplate.push(line.trim_end());
+        pos += 1;
+
+        // Blank lines between the separator and the original headers are
+        // only dropped when headers follow them
+        let mut header_pos = pos;
+        while header_pos < lines.len() && lines[header_pos].trim().is_empty() {
+            header_pos += 1;
+        }
+        if header_pos < lines.len() && header_name(lines[header_pos]).is_some() {
+            while header_pos < lines.len() && header_name(lines[header_pos]).is_some() {
+                result.boilerplate.push(lines[header_pos].trim_end());
+                header_pos += 1;
+            }
+            pos = header_pos;
+        }
+    }
+
+    result
+}
+
+/// Emits the forwarded boilerplate under the low-weight `fwd:` namespace:
+/// [`FORWARDED_TOKEN`], the client style (`fwd:gmail`) and the names of the
+/// original headers (`fwd:subject`), which carry no content of their own.
+pub fn forwarded_tokens(forwarded: &ForwardedText<'_>) -> Vec<Cow<'static, str>> {
+    let mut tokens = Vec::new();
+
+    if !forwarded.styles.is_empty() {
+        tokens.push(FORWARDED_TOKEN.into());
+    }
+    for style in &forwarded.styles {
+        tokens.push(
+            match style {
+                ForwardStyle::Gmail => "fwd:gmail",
+                ForwardStyle::Outlook => "fwd:outlook",
+                ForwardStyle::AppleMail => "fwd:applemail",
+            }
+            .into(),
+        );
+    }
+    for line in &forwarded.boilerplate {
+        if let Some(name) = header_name(line) {
+            tokens.push(format!("fwd:{name}").into());
+        }
+    }
+
+    tokens
+}
+
+fn forward_style(line: &str, following: &[&str]) -> Option<ForwardStyle> {
+    let label = line.trim_matches(|c: char| c == '-' || c.is_whitespace());
+
+    if line.starts_with('-') && label.eq_ignore_ascii_case("forwarded message") {
+        Some(ForwardStyle::Gmail)
+    } else if (line.starts_with('-') && label.eq_ignore_ascii_case("original message"))
+        || (line.len() >= 10
+            && line.bytes().all(|ch| ch == b'_')
+            && following
+                .iter()
+                .find(|line| !line.trim().is_empty())
+                .and_then(|line| header_name(line))
+                == Some("from"))
+    {
+        Some(ForwardStyle::Outlook)
+    } else if line.eq_ignore_ascii_case("begin forwarded message:") {
+        Some(ForwardStyle::AppleMail)
+    } else {
+        None
+    }
+}
+
+fn header_name(line: &str) -> Option<&'static str> {
+    let (name, _) = line.trim_start().split_once(':')?;
+    let name = name.trim_matches('*');
+    FORWARD_HEADERS
+        .iter()
+        .find(|header| header.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{forwarded_tokens, split_forwarded, ForwardStyle, FORWARDED_TOKEN};
+
+    #[test]
+    fn forwarded_boilerplate() {
+        let gmail = concat!(
+            "FYI, see below.\n",
+            "\n",
+            "---------- Forwarded message ---------\n",
+            "From: John Doe <john@example.org>\n",
+            "Date: Mon, 18 Mar 2024 at 10:15\n",
+            "Subject: Quarterly report\n",
+            "To: Jane Roe <jane@example.org>\n",
+            "\n",
+            "Please review the attached report.\n",
+        );
+        let forwarded = split_forwarded(gmail);
+        assert_eq!(forwarded.styles, vec![ForwardStyle::Gmail]);
+        assert_eq!(
+            forwarded.boilerplate,
+            vec![
+                "---------- Forwarded message ---------",
+                "From: John Doe <john@example.org>",
+                "Date: Mon, 18 Mar 2024 at 10:15",
+                "Subject: Quarterly report",
+                "To: Jane Roe <jane@example.org>",
+            ]
+        );
+        assert_eq!(
+            forwarded.text,
+            "FYI, see below.\n\n\nPlease review the attached report.\n"
+        );
+        assert_eq!(
+            forwarded_tokens(&forwarded),
+            vec![
+                FORWARDED_TOKEN,
+                "fwd:gmail",
+                "fwd:from",
+                "fwd:date",
+                "fwd:subject",
+                "fwd:to"
+            ]
+        );
+
+        for outlook in [
+            concat!(
+                "Forwarding this one.\r\n",
+                "\r\n",
+                "-----Original Message-----\r\n",
+                "From: John Doe <john@example.org>\r\n",
+                "Sent: Monday, March 18, 2024 10:15 AM\r\n",
+                "To: Jane Roe <jane@example.org>\r\n",
+                "Subject: Quarterly report\r\n",
+                "\r\n",
+                "Please review the attached report.\r\n",
+            ),
+            concat!(
+                "Forwarding this one.\r\n",
+                "\r\n",
+                "________________________________\r\n",
+                "From: John Doe <john@example.org>\r\n",
+                "Sent: Monday, March 18, 2024 10:15 AM\r\n",
+                "To: Jane Roe <jane@example.org>\r\n",
+                "Subject: Quarterly report\r\n",
+                "\r\n",
+                "Please review the attached report.\r\n",
+            ),
+        ] {
+            let forwarded = split_forwarded(outlook);
+            assert_eq!(forwarded.styles, vec![ForwardStyle::Outlook]);
+            assert_eq!(forwarded.boilerplate.len(), 5);
+            assert_eq!(
+                forwarded.boilerplate[1],
+                "From: John Doe <john@example.org>"
+            );
+            assert_eq!(
+                forwarded.text,
+                "Forwarding this one.\r\n\r\n\r\nPlease review the attached report.\r\n"
+            );
+            assert_eq!(
+                forwarded_tokens(&forwarded),
+                vec![
+                    FORWARDED_TOKEN,
+                    "fwd:outlook",
+                    "fwd:from",
+                    "fwd:sent",
+                    "fwd:to",
+                    "fwd:subject"
+                ]
+            );
+        }
+
+        // Underscore rules and regular text are left untouched
+        for text in [
+            "Sign here: ____________\nThanks\n",
+            "________________________________\nThe meeting is at noon.\n",
+            "From: the team, with thanks\n",
+        ] {
+            let forwarded = split_forwarded(text);
+            assert_eq!(forwarded.text, text);
+            assert!(forwarded.boilerplate.is_empty());
+            assert!(forwarded_tokens(&forwarded).is_empty());
+        }
+    }
+}
@@ -0,0 +1,190 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::io::{self, Write};
+
+use crate::tokenizers::osb::OsbToken;
+
+use super::TokenHash;
+
+// h1, h2 and idx
+const PAYLOAD_LEN: usize = 8 + 8 + 1;
+const PREFIX_LEN: usize = 4;
+
+/// Result of decoding the frame at the start of a buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// A decoded gram and the number of bytes its frame used.
+    Token(OsbToken<TokenHash>, usize),
+    /// The buffer ends before the frame does, more bytes are needed.
+    Incomplete,
+}
+
+/// Writes a gram as a length-prefixed frame: a little-endian `u32` payload
+/// length followed by `h1` and `h2` as little-endian `u64`s and `idx` as a
+/// single byte.
+pub fn write_frame(writer: &mut impl Write, token: &OsbToken<TokenHash>) -> io::Result<()> {
+    let idx = u8::try_from(token.idx)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Gram index out of range"))?;
+    let mut bytes = [0u8; PREFIX_LEN + PAYLOAD_LEN];
+    bytes[..4].copy_from_slice(&(PAYLOAD_LEN as u32).to_le_bytes());
+    bytes[4..12].copy_from_slice(&token.inner.h1.to_le_bytes());
+    bytes[12..20].copy_from_slice(&token.inner.h2.to_le_bytes());
+    bytes[20] = idx;
+    writer.write_all(&bytes)
+}
+
+/// Decodes the frame at the start of `bytes`, returning
+/// [`Frame::Incomplete`] when it has not been fully received yet so that
+/// callers reading from a stream can retry once more bytes arrive.
+pub fn read_frame(bytes: &[u8]) -> io::Result<Frame> {
+    let Some(prefix) = bytes.get(..PREFIX_LEN) else {
+        return Ok(Frame::Incomplete);
+    };
+    let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+    if len != PAYLOAD_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid gram frame length {len}"),
+        ));
+    }
+
+    match bytes.get(PREFIX_LEN..PREFIX_LEN + PAYLOAD_LEN) {
+        Some(payload) => Ok(Frame::Token(
+            OsbToken {
+                inner: TokenHash {
+                    h1: u64::from_le_bytes(payload[..8].try_into().unwrap()),
+                    h2: u64::from_le_bytes(payload[8..16].try_into().unwrap()),
+                },
+                idx: payload[16] as usize,
+            },
+            PREFIX_LEN + PAYLOAD_LEN,
+        )),
+        None => Ok(Frame::Incomplete),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{bayes::TokenHash, tokenizers::osb::OsbToken};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{read_frame, write_frame, Frame};
+
+    fn sample_grams() -> Vec<OsbToken<TokenHash>> {
+        (0..8)
+            .map(|n| OsbToken {
+                inner: TokenHash {
+                    h1: n,
+                    h2: u64::MAX - n,
+                },
+                idx: n as usize % 5,
+            })
+            .collect()
+    }
+
+    fn encode(grams: &[OsbToken<TokenHash>]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for gram in grams {
+            write_frame(&mut bytes, gram).unwrap();
+        }
+        bytes
+    }
+
+    // Decodes all complete frames, leaving any partial frame in the buffer
+    fn drain(buf: &mut Vec<u8>, grams: &mut Vec<OsbToken<TokenHash>>) {
+        while let Frame::Token(gram, len) = read_frame(buf).unwrap() {
+            grams.push(gram);
+            buf.drain(..len);
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_duplex_round_trip() {
+        let grams = sample_grams();
+        let bytes = encode(&grams);
+        let (mut client, mut server) = tokio::io::duplex(64);
+
+        let writer = tokio::spawn(async move {
+            client.write_all(&bytes).await.unwrap();
+        });
+
+        let mut buf = Vec::new();
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 50];
+        loop {
+            let len = server.read(&mut chunk).await.unwrap();
+            if len == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..len]);
+            drain(&mut buf, &mut received);
+        }
+        writer.await.unwrap();
+
+        assert!(buf.is_empty());
+        assert_eq!(received, grams);
+    }
+
+    #[test]
+    fn frame_split_reads() {
+        let grams = sample_grams();
+        let bytes = encode(&grams);
+        assert_eq!(bytes.len(), 8 * 21);
+        assert_eq!(
+            bytes[21..42],
+            [17, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 254, 255, 255, 255, 255, 255, 255, 255, 1]
+        );
+
+        let mut buf = Vec::new();
+        let mut received = Vec::new();
+        for byte in &bytes {
+            assert!(buf.len() < 21);
+            buf.push(*byte);
+            drain(&mut buf, &mut received);
+        }
+        assert!(buf.is_empty());
+        assert_eq!(received, grams);
+
+        assert_eq!(read_frame(&[]).unwrap(), Frame::Incomplete);
+        assert_eq!(read_frame(&bytes[..20]).unwrap(), Frame::Incomplete);
+        assert_eq!(
+            read_frame(&[3, 0, 0, 0, 1, 2, 3]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            write_frame(
+                &mut Vec::new(),
+                &OsbToken {
+                    inner: TokenHash::default(),
+                    idx: 256
+                }
+            )
+            .unwrap_err()
+            .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
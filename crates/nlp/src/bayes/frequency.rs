@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::HashMap, hash::BuildHasherDefault};
+
+use ahash::AHashSet;
+use nohash::NoHashHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::tokenizers::osb::OsbToken;
+
+use super::TokenHash;
+
+const DF_FORMAT_VERSION: u8 = 1;
+
+/// Counts the number of documents each gram appears in, independently of
+/// the spam/ham weights of the model.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+pub struct DocumentFrequencyCounter {
+    pub documents: u64,
+    pub frequencies: HashMap<TokenHash, u64, BuildHasherDefault<NoHashHasher<TokenHash>>>,
+}
+
+impl DocumentFrequencyCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a document, counting each distinct gram once.
+    pub fn add_document<T>(&mut self, tokens: T)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        self.documents += 1;
+
+        let mut seen = AHashSet::new();
+        for token in tokens {
+            if seen.insert(token.inner) {
+                *self.frequencies.entry(token.inner).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn document_frequency(&self, hash: &TokenHash) -> u64 {
+        self.frequencies.get(hash).copied().unwrap_or_default()
+    }
+
+    /// Smoothed inverse document frequency, `ln((1 + N) / (1 + df)) + 1`.
+    pub fn idf(&self, hash: &TokenHash) -> f64 {
+        ((1.0 + self.documents as f64) / (1.0 + self.document_frequency(hash) as f64)).ln() + 1.0
+    }
+
+    /// Combines the statistics of another shard into this counter.
+    pub fn merge(&mut self, other: &DocumentFrequencyCounter) {
+        self.documents += other.documents;
+        for (hash, frequency) in &other.frequencies {
+            *self.frequencies.entry(*hash).or_default() += frequency;
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + 8 + self.frequencies.len() * 24);
+        bytes.push(DF_FORMAT_VERSION);
+        bincode::serialize_into(&mut bytes, self).unwrap_or_default();
+        bytes
+    }
+
+    /// Deserializes a counter, returning `None` for unsupported versions or
+    /// corrupted data.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes.split_first() {
+            Some((&DF_FORMAT_VERSION, bytes)) => bincode::deserialize(bytes).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, TokenHash},
+        tokenizers::osb::{Gram, OsbTokenizer},
+    };
+
+    use super::DocumentFrequencyCounter;
+
+    fn counter(corpus: &[&str]) -> DocumentFrequencyCounter {
+        let suffixes = PublicSuffix::default();
+        let mut df = DocumentFrequencyCounter::new();
+        for text in corpus {
+            df.add_document(OsbTokenizer::new(BayesTokenizer::new(text, &suffixes), 1));
+        }
+        df
+    }
+
+    #[test]
+    fn document_frequency_round_trip() {
+        let df = counter(&["cheap pills", "cheap meeting"]);
+        assert_eq!(df.documents, 2);
+        assert_eq!(df.frequencies.len(), 3);
+
+        let bytes = df.to_bytes();
+        assert_eq!(DocumentFrequencyCounter::from_bytes(&bytes), Some(df));
+
+        // Unknown versions and corrupted data are rejected
+        let mut bad_version = bytes.clone();
+        bad_version[0] = u8::MAX;
+        assert_eq!(DocumentFrequencyCounter::from_bytes(&bad_version), None);
+        assert_eq!(DocumentFrequencyCounter::from_bytes(&bytes[..5]), None);
+        assert_eq!(DocumentFrequencyCounter::from_bytes(&[]), None);
+    }
+
+    #[test]
+    fn document_frequency_merge() {
+        let cheap = TokenHash::from(Gram::Uni { t1: "cheap" });
+        let pill = TokenHash::from(Gram::Uni { t1: "pill" });
+        let meeting = TokenHash::from(Gram::Uni { t1: "meet" });
+
+        // Repeated grams in a document are only counted once
+        let spam = counter(&["cheap pills", "cheap cheap"]);
+        let ham = counter(&["cheap meeting"]);
+        assert_eq!(spam.document_frequency(&cheap), 2);
+
+        let mut merged = spam.clone();
+        merged.merge(&ham);
+        assert_eq!(merged.documents, 3);
+        for (hash, frequency) in [(cheap, 3), (pill, 1), (meeting, 1)] {
+            assert_eq!(merged.document_frequency(&hash), frequency);
+        }
+
+        // ln((1 + 3) / (1 + df)) + 1
+        assert_eq!(merged.idf(&cheap), 1.0);
+        assert_eq!(merged.idf(&pill), 2.0f64.ln() + 1.0);
+        assert_eq!(
+            merged.idf(&TokenHash::from(Gram::Uni { t1: "unknown" })),
+            4.0f64.ln() + 1.0
+        );
+    }
+}
@@ -0,0 +1,253 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, hash::Hasher};
+
+use siphasher::sip128::{Hasher128, SipHasher24};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::tokenizers::osb::{Gram, OsbToken, OsbTokenizer};
+
+use super::TokenHash;
+
+/// Maps grams to the feature ids stored in the model.
+pub trait FeatureHasher {
+    fn hash(&self, gram: &Gram<'_>) -> TokenHash;
+}
+
+/// The unkeyed xxh3/farmhash pair used by `From<Gram> for TokenHash`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFeatureHasher;
+
+/// Hashes grams with SipHash-2-4 under a shared secret key.
+///
+/// This is pseudonymization, not encryption: anyone holding the key can
+/// recompute the id of a candidate word, while models trained by different
+/// parties with the same key remain compatible.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyedFeatureHasher {
+    hasher: SipHasher24,
+}
+
+/// Hashes grams with xxh3-128 under a per-model seed, tagging the hash with
+/// the arity of the gram and the length of each token. Unlike the other
+/// hashers, which join the tokens with a space, a unigram can't collide with
+/// a bigram whose tokens join to the same text, nor can bigrams that only
+/// differ in where the space falls (`"a b" + "c"` and `"a" + "b c"`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenHasher {
+    seed: u64,
+}
+
+pub struct HashedGrams<'h, 'x, I, R, H>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+    H: FeatureHasher,
+{
+    tokenizer: OsbTokenizer<'x, I, R>,
+    hasher: &'h H,
+}
+
+impl FeatureHasher for DefaultFeatureHasher {
+    fn hash(&self, gram: &Gram<'_>) -> TokenHash {
+        TokenHash::from(gram.clone())
+    }
+}
+
+impl KeyedFeatureHasher {
+    pub fn new(key: &[u8; 16]) -> Self {
+        KeyedFeatureHasher {
+            hasher: SipHasher24::new_with_key(key),
+        }
+    }
+}
+
+impl FeatureHasher for KeyedFeatureHasher {
+    fn hash(&self, gram: &Gram<'_>) -> TokenHash {
+        let mut hasher = self.hasher;
+        match gram {
+            Gram::Uni { t1 } => {
+                hasher.write(t1.as_bytes());
+            }
+            Gram::Bi { t1, t2 } => {
+                hasher.write(t1.as_bytes());
+                hasher.write(b" ");
+                hasher.write(t2.as_bytes());
+            }
+            Gram::Tri { t1, t2, t3 } => {
+                hasher.write(t1.as_bytes());
+                hasher.write(b" ");
+                hasher.write(t2.as_bytes());
+                hasher.write(b" ");
+                hasher.write(t3.as_bytes());
+            }
+        }
+        let hash = hasher.finish128();
+        TokenHash {
+            h1: hash.h1,
+            h2: hash.h2,
+        }
+    }
+}
+
+impl TokenHasher {
+    pub fn new(seed: u64) -> Self {
+        TokenHasher { seed }
+    }
+}
+
+impl FeatureHasher for TokenHasher {
+    fn hash(&self, gram: &Gram<'_>) -> TokenHash {
+        let tokens = match gram {
+            Gram::Uni { t1 } => &[*t1][..],
+            Gram::Bi { t1, t2 } => &[*t1, *t2][..],
+            Gram::Tri { t1, t2, t3 } => &[*t1, *t2, *t3][..],
+        };
+        let mut hasher = Xxh3::with_seed(self.seed);
+        hasher.update(&[tokens.len() as u8]);
+        for token in tokens {
+            hasher.update(&(token.len() as u64).to_le_bytes());
+            hasher.update(token.as_bytes());
+        }
+        let hash = hasher.digest128();
+        TokenHash {
+            h1: hash as u64,
+            h2: (hash >> 64) as u64,
+        }
+    }
+}
+
+impl<'h, 'x, I, R, H> HashedGrams<'h, 'x, I, R, H>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+    H: FeatureHasher,
+{
+    pub fn new(tokenizer: OsbTokenizer<'x, I, R>, hasher: &'h H) -> Self {
+        HashedGrams { tokenizer, hasher }
+    }
+}
+
+impl<'h, 'x, I, R, H> Iterator for HashedGrams<'h, 'x, I, R, H>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+    H: FeatureHasher,
+{
+    type Item = OsbToken<TokenHash>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hasher = self.hasher;
+        self.tokenizer.next_gram(|gram| hasher.hash(&gram))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{
+        bayes::TokenHash,
+        tokenizers::osb::{Gram, OsbTokenizer},
+    };
+
+    use super::{
+        DefaultFeatureHasher, FeatureHasher, HashedGrams, KeyedFeatureHasher, TokenHasher,
+    };
+
+    fn hashes(text: &str, hasher: &impl FeatureHasher) -> Vec<TokenHash> {
+        HashedGrams::new(
+            OsbTokenizer::<_, TokenHash>::new(text.split_ascii_whitespace().map(Cow::from), 5),
+            hasher,
+        )
+        .map(|t| t.inner)
+        .collect()
+    }
+
+    #[test]
+    fn keyed_feature_hasher() {
+        let text = "buy cheap pills now";
+        let key = *b"shared-org-key-1";
+
+        // Two organizations sharing the same key produce identical ids
+        let org_a = KeyedFeatureHasher::new(&key);
+        let org_b = KeyedFeatureHasher::new(&key);
+        assert_eq!(hashes(text, &org_a), hashes(text, &org_b));
+
+        // A different key produces unrelated ids
+        let other = KeyedFeatureHasher::new(b"another-org-key!");
+        let keyed = hashes(text, &org_a);
+        assert!(keyed.iter().zip(hashes(text, &other)).all(|(a, b)| *a != b));
+
+        // Keyed ids can't be recomputed from the word with the unkeyed hasher
+        let unkeyed = hashes(text, &DefaultFeatureHasher);
+        assert!(keyed.iter().all(|id| !unkeyed.contains(id)));
+        assert_eq!(
+            unkeyed[0],
+            TokenHash::from(Gram::Uni { t1: "buy" }),
+            "default hasher must match the model hashing"
+        );
+    }
+
+    #[test]
+    fn token_hasher() {
+        let hasher = TokenHasher::new(0x5eed);
+        let uni = Gram::Uni { t1: "the cat" };
+        let bi = Gram::Bi {
+            t1: "the",
+            t2: "cat",
+        };
+
+        // Joined with a space, both grams share the same text and hash
+        assert_eq!(
+            DefaultFeatureHasher.hash(&uni),
+            DefaultFeatureHasher.hash(&bi)
+        );
+        assert_ne!(hasher.hash(&uni), hasher.hash(&bi));
+        assert_ne!(
+            hasher.hash(&Gram::Bi { t1: "a b", t2: "c" }),
+            hasher.hash(&Gram::Bi { t1: "a", t2: "b c" })
+        );
+        assert_ne!(
+            hasher.hash(&Gram::Tri {
+                t1: "the",
+                t2: "cat",
+                t3: "sat"
+            }),
+            hasher.hash(&Gram::Bi {
+                t1: "the cat",
+                t2: "sat"
+            })
+        );
+
+        // Deterministic for a seed, unrelated across seeds
+        assert_eq!(hasher.hash(&bi), TokenHasher::new(0x5eed).hash(&bi));
+        assert_ne!(hasher.hash(&bi), TokenHasher::new(0).hash(&bi));
+        let text = "buy cheap pills now";
+        assert_eq!(
+            hashes(text, &hasher),
+            hashes(text, &TokenHasher::new(0x5eed))
+        );
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::HashSet,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::tokenizers::osb::OsbToken;
+
+/// Filters a token stream down to the features missing from `seen`, adding
+/// them to it as they are emitted, so that a model updated incrementally
+/// from a live feed only processes features it hasn't seen before. Repeated
+/// features within the stream are only emitted once, so the output depends
+/// only on the input and the contents of `seen`.
+pub struct UnseenFilter<'s, I, T, S> {
+    iter: I,
+    seen: &'s mut HashSet<T, S>,
+}
+
+impl<'s, I, T, S> UnseenFilter<'s, I, T, S> {
+    pub fn new(iter: I, seen: &'s mut HashSet<T, S>) -> Self {
+        UnseenFilter { iter, seen }
+    }
+}
+
+impl<I, T, S> Iterator for UnseenFilter<'_, I, T, S>
+where
+    I: Iterator<Item = OsbToken<T>>,
+    T: Hash + Eq + Clone,
+    S: BuildHasher,
+{
+    type Item = OsbToken<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let seen = &mut *self.seen;
+        self.iter
+            .by_ref()
+            .find(|token| seen.insert(token.inner.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashSet};
+
+    use crate::tokenizers::osb::{OsbToken, OsbTokenizer};
+
+    use super::UnseenFilter;
+
+    #[test]
+    fn unseen_filter() {
+        let tokenize = |text: &'static str| {
+            OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 2)
+        };
+        let token = |inner: &str, idx| OsbToken {
+            inner: inner.to_string(),
+            idx,
+        };
+        let mut seen = HashSet::new();
+
+        // Repeated grams are only emitted once
+        let text = "cheap pills cheap pills today";
+        assert_eq!(tokenize(text).count(), 9);
+        assert_eq!(
+            UnseenFilter::new(tokenize(text), &mut seen).collect::<Vec<_>>(),
+            [
+                token("cheap", 0),
+                token("cheap pills", 1),
+                token("pills", 0),
+                token("pills cheap", 1),
+                token("pills today", 1),
+                token("today", 0),
+            ]
+        );
+        assert_eq!(seen.len(), 6);
+
+        // Nothing new the second time
+        assert_eq!(UnseenFilter::new(tokenize(text), &mut seen).count(), 0);
+
+        // Only the grams missing from the seen set are emitted, deterministically
+        for _ in 0..2 {
+            let mut seen = seen.clone();
+            assert_eq!(
+                UnseenFilter::new(tokenize("today only"), &mut seen).collect::<Vec<_>>(),
+                [token("today only", 1), token("only", 0)]
+            );
+            assert_eq!(seen.len(), 8);
+        }
+    }
+}
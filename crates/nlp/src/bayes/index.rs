@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::HashMap, hash::BuildHasherDefault};
+
+use nohash::NoHashHasher;
+
+use crate::tokenizers::osb::OsbToken;
+
+use super::TokenHash;
+
+pub type DocId = u64;
+
+/// Posting lists keyed by the `h1` hash of each gram.
+pub type PostingLists = HashMap<u64, Vec<DocId>, BuildHasherDefault<NoHashHasher<u64>>>;
+
+/// Builds an inverted index mapping grams to the documents they appear in.
+#[derive(Debug, Default)]
+pub struct IndexBuilder {
+    postings: PostingLists,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T>(&mut self, doc_id: DocId, tokens: T)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        for token in tokens {
+            let postings = self.postings.entry(token.inner.h1).or_default();
+            // Avoid growing the list when a gram repeats within a document
+            if postings.last() != Some(&doc_id) {
+                postings.push(doc_id);
+            }
+        }
+    }
+
+    /// Returns the posting lists with sorted and deduplicated document ids.
+    pub fn build(mut self) -> PostingLists {
+        for postings in self.postings.values_mut() {
+            postings.sort_unstable();
+            postings.dedup();
+        }
+        self.postings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, TokenHash},
+        tokenizers::osb::{Gram, OsbTokenizer},
+    };
+
+    use super::IndexBuilder;
+
+    #[test]
+    fn inverted_index() {
+        let suffixes = PublicSuffix::default();
+        let mut builder = IndexBuilder::new();
+
+        for (doc_id, text) in [
+            (20, "claim your free prize now"),
+            (10, "your invoice is attached, claim your refund"),
+            (20, "claim it again"),
+        ] {
+            builder.add(
+                doc_id,
+                OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, &suffixes), 5),
+            );
+        }
+        let index = builder.build();
+
+        let claim = TokenHash::from(Gram::Uni { t1: "claim" }).h1;
+        let prize = TokenHash::from(Gram::Uni { t1: "prize" }).h1;
+        let missing = TokenHash::from(Gram::Uni { t1: "meeting" }).h1;
+        assert_eq!(index.get(&claim), Some(&vec![10, 20]));
+        assert_eq!(index.get(&prize), Some(&vec![20]));
+        assert_eq!(index.get(&missing), None);
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::tokenizers::osb::{OsbToken, OsbTokenizer};
+
+use super::{tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash, WeightStore};
+
+/// OSB window sizes used for each source of a message. Headers are short
+/// and usually benefit from a smaller window than the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSizes {
+    pub header: usize,
+    pub body: usize,
+}
+
+impl WindowSizes {
+    pub fn uniform(window_size: usize) -> Self {
+        WindowSizes {
+            header: window_size,
+            body: window_size,
+        }
+    }
+}
+
+/// Tokenizes the headers and the body of a message separately, each with
+/// its own window size, so that no gram spans both sources.
+pub fn message_tokens<'x>(
+    headers: &'x str,
+    body: &'x str,
+    suffixes: &'x PublicSuffix,
+    windows: WindowSizes,
+) -> impl Iterator<Item = OsbToken<TokenHash>> + 'x {
+    OsbTokenizer::new(BayesTokenizer::new(headers, suffixes), windows.header).chain(
+        OsbTokenizer::new(BayesTokenizer::new(body, suffixes), windows.body),
+    )
+}
+
+impl BayesModel {
+    pub fn train_message(
+        &mut self,
+        headers: &str,
+        body: &str,
+        is_spam: bool,
+        suffixes: &PublicSuffix,
+        windows: WindowSizes,
+    ) {
+        self.train(message_tokens(headers, body, suffixes, windows), is_spam);
+    }
+}
+
+impl BayesClassifier {
+    pub fn classify_message(
+        &self,
+        model: &impl WeightStore,
+        headers: &str,
+        body: &str,
+        suffixes: &PublicSuffix,
+        windows: WindowSizes,
+    ) -> Option<f64> {
+        self.classify(
+            message_tokens(headers, body, suffixes, windows).filter_map(|t| {
+                OsbToken {
+                    inner: model.weights(&t.inner)?,
+                    idx: t.idx,
+                }
+                .into()
+            }),
+            model.ham_learns(),
+            model.spam_learns(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{BayesClassifier, BayesModel, TokenHash},
+        tokenizers::osb::{Gram, OsbToken},
+    };
+
+    use super::{message_tokens, WindowSizes};
+
+    #[test]
+    fn per_source_windows() {
+        let suffixes = PublicSuffix::default();
+        let windows = WindowSizes { header: 1, body: 2 };
+        let token = |gram, idx| OsbToken {
+            inner: TokenHash::from(gram),
+            idx,
+        };
+
+        // Headers only produce unigrams, and no gram spans both sources
+        assert_eq!(
+            message_tokens("Cheap pills", "cheap pills", &suffixes, windows).collect::<Vec<_>>(),
+            vec![
+                token(Gram::Uni { t1: "cheap" }, 0),
+                token(Gram::Uni { t1: "pill" }, 0),
+                token(Gram::Uni { t1: "cheap" }, 0),
+                token(
+                    Gram::Bi {
+                        t1: "cheap",
+                        t2: "pill"
+                    },
+                    1
+                ),
+                token(Gram::Uni { t1: "pill" }, 0),
+            ]
+        );
+
+        // Training and classification use the same per-source grams
+        let mut model = BayesModel::default();
+        for (headers, body, is_spam) in [
+            ("cheap", "pills", true),
+            ("cheap", "pills", true),
+            ("team", "meeting", false),
+            ("team", "meeting", false),
+        ] {
+            model.train_message(headers, body, is_spam, &suffixes, windows);
+        }
+        assert_eq!(model.weights.len(), 4);
+
+        // Two grams at 7/8 combined with two degrees of freedom
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let score = classifier
+            .classify_message(&model, "cheap", "pills", &suffixes, windows)
+            .unwrap();
+        assert!((score - 0.944744).abs() < 1e-6, "{score}");
+        assert_eq!(
+            classifier.classify_message(&model, "", "pills", &suffixes, windows),
+            Some(0.875)
+        );
+    }
+}
@@ -28,10 +28,33 @@ use serde::{Deserialize, Serialize};
 
 use crate::tokenizers::osb::Gram;
 
+use self::calibrate::PlattCalibration;
+
+pub mod accounts;
+pub mod batch;
 pub mod cache;
+pub mod calibrate;
+pub mod chars;
 pub mod classify;
+pub mod cluster;
+pub mod disposable;
+pub mod eval;
+pub mod explain;
+pub mod features;
+pub mod forward;
+pub mod frame;
+pub mod frequency;
+pub mod hasher;
+pub mod incremental;
+pub mod index;
+pub mod message;
+pub mod prune;
+pub mod sketch;
+pub mod streaming;
+pub mod synonyms;
 pub mod tokenize;
 pub mod train;
+pub mod weight;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct BayesModel {
@@ -46,6 +69,15 @@ pub struct BayesClassifier {
     pub min_tokens: u32,
     pub min_prob_strength: f64,
     pub min_learns: u32,
+    /// Rescales the combined log-probabilities to a fixed feature count before
+    /// the chi-square test, `ln_p' = ln_p * N / n` where `n` is the number of
+    /// processed tokens and `N` is `LENGTH_NORM_TOKENS`, so that short and long
+    /// messages with the same spam density produce comparable scores.
+    #[serde(default)]
+    pub length_normalize: bool,
+    /// Maps raw scores to calibrated probabilities, see `calibrate`.
+    #[serde(default)]
+    pub calibration: Option<PlattCalibration>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
@@ -60,6 +92,34 @@ pub struct Weights {
     pub ham: u32,
 }
 
+/// Signed change to the weights of a gram, see `BayesModel::apply_deltas`.
+#[derive(Debug, Serialize, Deserialize, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct CountDelta {
+    pub spam: i32,
+    pub ham: i32,
+}
+
+/// Per-gram weight storage that can be used for classification.
+pub trait WeightStore {
+    fn weights(&self, token: &TokenHash) -> Option<Weights>;
+    fn spam_learns(&self) -> u32;
+    fn ham_learns(&self) -> u32;
+}
+
+impl WeightStore for BayesModel {
+    fn weights(&self, token: &TokenHash) -> Option<Weights> {
+        self.weights.get(token).copied()
+    }
+
+    fn spam_learns(&self) -> u32 {
+        self.spam_learns
+    }
+
+    fn ham_learns(&self) -> u32 {
+        self.ham_learns
+    }
+}
+
 impl BayesClassifier {
     pub fn new() -> Self {
         BayesClassifier {
@@ -67,6 +127,8 @@ impl BayesClassifier {
             min_tokens: 11,
             min_prob_strength: 0.05,
             min_learns: 200,
+            length_normalize: false,
+            calibration: None,
         }
     }
 }
@@ -94,6 +156,18 @@ impl From<Gram<'_>> for TokenHash {
                     h2: farmhash::hash64(&buf),
                 }
             }
+            Gram::Tri { t1, t2, t3 } => {
+                let mut buf = Vec::with_capacity(t1.len() + t2.len() + t3.len() + 2);
+                buf.extend_from_slice(t1.as_bytes());
+                buf.push(b' ');
+                buf.extend_from_slice(t2.as_bytes());
+                buf.push(b' ');
+                buf.extend_from_slice(t3.as_bytes());
+                TokenHash {
+                    h1: xxhash_rust::xxh3::xxh3_64(&buf),
+                    h2: farmhash::hash64(&buf),
+                }
+            }
         }
     }
 }
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use super::{BayesModel, Weights};
+
+impl BayesModel {
+    /// Prunes the least informative grams, ranked by `information_gain`,
+    /// until the serialized model is at most `target` bytes. Returns the
+    /// final serialized size, which only exceeds `target` when it is smaller
+    /// than the size of an empty model.
+    pub fn shrink_to_bytes(&mut self, target: usize) -> usize {
+        let empty_size = serialized_size(&BayesModel::default());
+        let mut size = serialized_size(self);
+        if size <= target {
+            return size;
+        }
+
+        // Rank grams from least to most informative
+        let mut ranked = self
+            .weights
+            .iter()
+            .map(|(token, weights)| {
+                (
+                    information_gain(weights, self.spam_learns, self.ham_learns),
+                    *token,
+                )
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_unstable_by(|(a_ig, a), (b_ig, b)| {
+            a_ig.total_cmp(b_ig)
+                .then_with(|| (a.h1, a.h2).cmp(&(b.h1, b.h2)))
+        });
+        let mut ranked = ranked.into_iter().map(|(_, token)| token);
+
+        while size > target && !self.weights.is_empty() {
+            // Estimate how many grams to drop from the average entry size
+            let entry_size = (size - empty_size).div_ceil(self.weights.len()).max(1);
+            for token in ranked.by_ref().take((size - target).div_ceil(entry_size)) {
+                self.weights.remove(&token);
+            }
+            size = serialized_size(self);
+        }
+
+        size
+    }
+}
+
+/// Returns the information gain, in bits, that the presence of a gram with
+/// the given weights provides about the class of a message.
+pub fn information_gain(weights: &Weights, spam_learns: u32, ham_learns: u32) -> f64 {
+    let spam_learns = spam_learns as f64;
+    let ham_learns = ham_learns as f64;
+    let total = spam_learns + ham_learns;
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    // Weights can exceed the learn count when a gram repeats within a message
+    let spam = (weights.spam as f64).min(spam_learns);
+    let ham = (weights.ham as f64).min(ham_learns);
+    let present = spam + ham;
+    let absent = total - present;
+
+    entropy(spam_learns, ham_learns)
+        - (present / total) * entropy(spam, ham)
+        - (absent / total) * entropy(spam_learns - spam, ham_learns - ham)
+}
+
+fn entropy(a: f64, b: f64) -> f64 {
+    let total = a + b;
+    [a, b]
+        .into_iter()
+        .filter(|&n| n > 0.0)
+        .map(|n| {
+            let p = n / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn serialized_size(model: &BayesModel) -> usize {
+    bincode::serialized_size(model).unwrap_or_default() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bayes::{BayesModel, TokenHash, Weights};
+
+    use super::{information_gain, serialized_size};
+
+    #[test]
+    fn shrink_to_bytes() {
+        let spam = TokenHash { h1: 1, h2: 1 };
+        let ham = TokenHash { h1: 2, h2: 2 };
+        let even = TokenHash { h1: 3, h2: 3 };
+        let weak = TokenHash { h1: 4, h2: 4 };
+        let mut model = BayesModel {
+            spam_learns: 2,
+            ham_learns: 2,
+            ..Default::default()
+        };
+        for (token, spam, ham) in [(spam, 2, 0), (ham, 0, 2), (even, 1, 1), (weak, 1, 0)] {
+            model.weights.insert(token, Weights { spam, ham });
+        }
+
+        // Each entry is a 16 byte hash and an 8 byte weight
+        let original_size = serialized_size(&model);
+        let empty_size = serialized_size(&BayesModel::default());
+        assert_eq!(original_size, empty_size + 4 * 24);
+
+        // The least informative grams go first
+        let size = model.shrink_to_bytes(empty_size + 2 * 24);
+        assert_eq!(size, empty_size + 2 * 24);
+        let mut kept = model.weights.keys().copied().collect::<Vec<_>>();
+        kept.sort_unstable_by_key(|token| token.h1);
+        assert_eq!(kept, [spam, ham]);
+        assert_eq!((model.spam_learns, model.ham_learns), (2, 2));
+
+        // Models already under the target are left untouched
+        assert_eq!(model.shrink_to_bytes(original_size), size);
+        assert_eq!(model.weights.len(), 2);
+
+        // Targets below the empty model size drop every gram
+        assert_eq!(model.shrink_to_bytes(0), empty_size);
+        assert!(model.weights.is_empty());
+    }
+
+    #[test]
+    fn information_gain_ranking() {
+        assert_eq!(information_gain(&Weights { spam: 2, ham: 0 }, 2, 2), 1.0);
+        assert_eq!(information_gain(&Weights { spam: 1, ham: 1 }, 2, 2), 0.0);
+        assert_eq!(information_gain(&Weights { spam: 0, ham: 0 }, 0, 0), 0.0);
+
+        // Present in one of four messages, absent from one spam and two ham
+        // messages: 1 - 3/4 * H(1/3)
+        let weak = information_gain(&Weights { spam: 1, ham: 0 }, 2, 2);
+        assert!((weak - (1.5 - 0.75 * 3f64.log2())).abs() < 1e-12, "{weak}");
+
+        // Repeated grams are capped at the learn count
+        assert_eq!(information_gain(&Weights { spam: 5, ham: 0 }, 2, 2), 1.0);
+    }
+}
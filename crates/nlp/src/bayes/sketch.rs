@@ -0,0 +1,263 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::tokenizers::osb::OsbToken;
+
+use super::{TokenHash, WeightStore, Weights};
+
+/// Approximate weight storage backed by a count-min sketch, using a fixed
+/// `width * depth` table of counters regardless of the number of grams.
+///
+/// Estimates never undercount. With `width = ceil(e / epsilon)` and
+/// `depth = ceil(ln(1 / delta))`, each spam or ham count overestimates the
+/// true count by at most `epsilon * N` with probability `1 - delta`, where
+/// `N` is the total number of grams trained for that class.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountMinBackend {
+    width: usize,
+    depth: usize,
+    counters: Vec<Weights>,
+    pub spam_learns: u32,
+    pub ham_learns: u32,
+}
+
+impl CountMinBackend {
+    pub fn new(width: usize, depth: usize) -> Self {
+        let width = width.max(1);
+        let depth = depth.max(1);
+
+        CountMinBackend {
+            width,
+            depth,
+            counters: vec![Weights::default(); width * depth],
+            spam_learns: 0,
+            ham_learns: 0,
+        }
+    }
+
+    /// Sizes the sketch for an overestimation of at most `epsilon * N` with
+    /// probability `1 - delta`.
+    pub fn with_error_bounds(epsilon: f64, delta: f64) -> Self {
+        Self::new(
+            (std::f64::consts::E / epsilon).ceil() as usize,
+            (1.0 / delta).ln().ceil() as usize,
+        )
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn train<T>(&mut self, tokens: T, is_spam: bool)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        if is_spam {
+            self.spam_learns += 1;
+        } else {
+            self.ham_learns += 1;
+        }
+
+        for token in tokens {
+            for pos in self.positions(&token.inner) {
+                let hs = &mut self.counters[pos];
+                if is_spam {
+                    hs.spam = hs.spam.saturating_add(1);
+                } else {
+                    hs.ham = hs.ham.saturating_add(1);
+                }
+            }
+        }
+    }
+
+    pub fn untrain<T>(&mut self, tokens: T, is_spam: bool)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        if is_spam {
+            self.spam_learns = self.spam_learns.saturating_sub(1);
+        } else {
+            self.ham_learns = self.ham_learns.saturating_sub(1);
+        }
+
+        for token in tokens {
+            for pos in self.positions(&token.inner) {
+                let hs = &mut self.counters[pos];
+                if is_spam {
+                    hs.spam = hs.spam.saturating_sub(1);
+                } else {
+                    hs.ham = hs.ham.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Returns the estimated weights of a gram, taking the minimum spam and
+    /// ham counts across all rows.
+    pub fn estimate(&self, token: &TokenHash) -> Weights {
+        let mut weights = Weights {
+            spam: u32::MAX,
+            ham: u32::MAX,
+        };
+
+        for pos in self.positions(token) {
+            let hs = &self.counters[pos];
+            weights.spam = weights.spam.min(hs.spam);
+            weights.ham = weights.ham.min(hs.ham);
+        }
+
+        weights
+    }
+
+    // Kirsch-Mitzenmacher double hashing, one counter per row
+    fn positions(&self, token: &TokenHash) -> impl Iterator<Item = usize> {
+        let width = self.width;
+        let TokenHash { h1, h2 } = *token;
+
+        (0..self.depth).map(move |row| {
+            row * width + (h1.wrapping_add((row as u64).wrapping_mul(h2)) % width as u64) as usize
+        })
+    }
+}
+
+impl WeightStore for CountMinBackend {
+    fn weights(&self, token: &TokenHash) -> Option<Weights> {
+        let weights = self.estimate(token);
+        if weights.spam > 0 || weights.ham > 0 {
+            Some(weights)
+        } else {
+            None
+        }
+    }
+
+    fn spam_learns(&self) -> u32 {
+        self.spam_learns
+    }
+
+    fn ham_learns(&self) -> u32 {
+        self.ham_learns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{
+            tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash, WeightStore, Weights,
+        },
+        tokenizers::osb::{OsbToken, OsbTokenizer},
+    };
+
+    use super::CountMinBackend;
+
+    #[test]
+    fn count_min_sketch() {
+        let token = |h1, h2| OsbToken {
+            inner: TokenHash { h1, h2 },
+            idx: 0,
+        };
+
+        // Rows map a gram to h1 % 4 and 4 + (h1 + h2) % 4
+        let mut sketch = CountMinBackend::new(4, 2);
+        sketch.train([token(0, 1)], true);
+        sketch.train([token(0, 1)], true);
+        sketch.train([token(1, 1)], true);
+        sketch.train([token(1, 1)], false);
+        assert_eq!((sketch.spam_learns(), sketch.ham_learns()), (3, 1));
+        assert_eq!(
+            sketch.estimate(&TokenHash { h1: 0, h2: 1 }),
+            Weights { spam: 2, ham: 0 }
+        );
+        assert_eq!(
+            sketch.estimate(&TokenHash { h1: 1, h2: 1 }),
+            Weights { spam: 1, ham: 1 }
+        );
+
+        // An untrained gram sharing a counter with each of them in every row
+        // is overestimated, one sharing none is not found
+        assert_eq!(
+            sketch.weights(&TokenHash { h1: 4, h2: 2 }),
+            Some(Weights { spam: 1, ham: 0 })
+        );
+        assert_eq!(sketch.weights(&TokenHash { h1: 2, h2: 1 }), None);
+
+        sketch.untrain([token(0, 1)], true);
+        assert_eq!(
+            sketch.estimate(&TokenHash { h1: 0, h2: 1 }),
+            Weights { spam: 1, ham: 0 }
+        );
+        assert_eq!(sketch.spam_learns(), 2);
+    }
+
+    #[test]
+    fn count_min_classify() {
+        let suffixes = PublicSuffix::default();
+        let classifier = BayesClassifier {
+            min_tokens: 1,
+            min_learns: 1,
+            ..BayesClassifier::new()
+        };
+        let mut exact = BayesModel::default();
+        let mut sketch = CountMinBackend::with_error_bounds(0.001, 0.01);
+        assert_eq!((sketch.width(), sketch.depth()), (2719, 5));
+
+        for (text, is_spam) in [
+            ("pills", true),
+            ("pills", true),
+            ("meeting", false),
+            ("meeting", false),
+        ] {
+            let tokens = OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, &suffixes), 1)
+                .collect::<Vec<_>>();
+            exact.train(tokens.clone(), is_spam);
+            sketch.train(tokens, is_spam);
+        }
+
+        // Without collisions the sketch matches the exact counts
+        for (hash, weights) in &exact.weights {
+            assert_eq!(sketch.estimate(hash), *weights);
+        }
+        for (text, expected) in [
+            ("pills", Some(0.875)),
+            ("meeting", Some(0.125)),
+            ("unknown", None),
+        ] {
+            assert_eq!(
+                classifier.classify_text(&exact, text, &suffixes, 1),
+                expected
+            );
+            assert_eq!(
+                classifier.classify_text(&sketch, text, &suffixes, 1),
+                expected
+            );
+        }
+    }
+}
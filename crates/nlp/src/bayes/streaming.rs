@@ -0,0 +1,185 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::collections::VecDeque;
+
+use utils::suffixlist::PublicSuffix;
+
+use crate::{
+    language::Language,
+    tokenizers::osb::{Gram, OsbToken},
+};
+
+use super::tokenize::BayesTokenizer;
+
+/// Incremental OSB tokenizer for text that grows over time, such as a draft
+/// being typed. Each call to [`StreamingTokenizer::push`] returns only the
+/// grams that became stable since the previous call, so that the
+/// concatenation of all returned grams followed by those returned by
+/// [`StreamingTokenizer::finish`] equals a full tokenization of the text.
+///
+/// Text after the last whitespace is held back, as the trailing word may
+/// still be incomplete, and a head token is only emitted once the
+/// `window_size - 1` tokens following it are known.
+pub struct StreamingTokenizer<'x> {
+    suffixes: &'x PublicSuffix,
+    language: Language,
+    window_size: usize,
+    pending: String,
+    window: VecDeque<String>,
+}
+
+impl<'x> StreamingTokenizer<'x> {
+    pub fn new(language: Language, suffixes: &'x PublicSuffix, window_size: usize) -> Self {
+        Self {
+            suffixes,
+            language,
+            window_size,
+            pending: String::new(),
+            window: VecDeque::with_capacity(window_size),
+        }
+    }
+
+    /// Appends `text` and returns the newly stable grams.
+    pub fn push<R>(&mut self, text: &str) -> Vec<OsbToken<R>>
+    where
+        R: for<'y> From<Gram<'y>>,
+    {
+        let mut grams = Vec::new();
+        self.pending.push_str(text);
+
+        if let Some(pos) = self.pending.rfind(char::is_whitespace) {
+            let partial = self.pending.split_off(pos);
+            let stable = std::mem::replace(&mut self.pending, partial);
+            self.tokenize(&stable, &mut grams);
+        }
+
+        grams
+    }
+
+    /// Flushes the held trailing word and the remaining head tokens, which
+    /// emit their shorter windows, leaving the tokenizer ready to be reused.
+    pub fn finish<R>(&mut self) -> Vec<OsbToken<R>>
+    where
+        R: for<'y> From<Gram<'y>>,
+    {
+        let mut grams = Vec::new();
+        let pending = std::mem::take(&mut self.pending);
+        self.tokenize(&pending, &mut grams);
+
+        while !self.window.is_empty() {
+            self.emit_head(&mut grams);
+        }
+
+        grams
+    }
+
+    fn tokenize<R>(&mut self, text: &str, grams: &mut Vec<OsbToken<R>>)
+    where
+        R: for<'y> From<Gram<'y>>,
+    {
+        for token in BayesTokenizer::with_language(text, self.language, self.suffixes) {
+            self.window.push_back(token.into_owned());
+            if self.window.len() >= self.window_size {
+                self.emit_head(grams);
+            }
+        }
+    }
+
+    fn emit_head<R>(&mut self, grams: &mut Vec<OsbToken<R>>)
+    where
+        R: for<'y> From<Gram<'y>>,
+    {
+        if let Some(t1) = self.window.pop_front() {
+            grams.push(OsbToken {
+                inner: R::from(Gram::Uni { t1: &t1 }),
+                idx: 0,
+            });
+            for (idx, t2) in self.window.iter().enumerate() {
+                grams.push(OsbToken {
+                    inner: R::from(Gram::Bi { t1: &t1, t2 }),
+                    idx: idx + 1,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::tokenize::BayesTokenizer, language::Language, tokenizers::osb::OsbTokenizer,
+    };
+
+    use super::StreamingTokenizer;
+
+    #[test]
+    fn streaming_tokenizer() {
+        let suffixes = PublicSuffix::from("com");
+        let text = concat!(
+            "Dear friend, claim your FREE prize of $1,000,000 today at ",
+            "https://winner.example.com/claim or write to prize@example.com ",
+            "before the offer expires. Act now!"
+        );
+        let expected = OsbTokenizer::<_, String>::new(
+            BayesTokenizer::with_language(text, Language::English, &suffixes),
+            5,
+        )
+        .collect::<Vec<_>>();
+
+        for chunk_size in [1, 3, 7, 64] {
+            let mut tokenizer = StreamingTokenizer::new(Language::English, &suffixes, 5);
+            let mut grams = Vec::new();
+            let mut chars = text.chars().peekable();
+            while chars.peek().is_some() {
+                let chunk = chars.by_ref().take(chunk_size).collect::<String>();
+                grams.extend(tokenizer.push::<String>(&chunk));
+            }
+            grams.extend(tokenizer.finish::<String>());
+
+            assert_eq!(grams, expected, "failed for chunk size {chunk_size}");
+        }
+
+        // The partially typed trailing word is held until the next boundary
+        let mut tokenizer = StreamingTokenizer::new(Language::English, &suffixes, 1);
+        assert_eq!(
+            tokenizer
+                .push::<String>("hello wor")
+                .into_iter()
+                .map(|t| t.inner)
+                .collect::<Vec<_>>(),
+            ["hello"]
+        );
+        assert!(tokenizer.push::<String>("l").is_empty());
+        assert_eq!(
+            tokenizer
+                .push::<String>("d ")
+                .into_iter()
+                .map(|t| t.inner)
+                .collect::<Vec<_>>(),
+            ["world"]
+        );
+        assert!(tokenizer.finish::<String>().is_empty());
+    }
+}
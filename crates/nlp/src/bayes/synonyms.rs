@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+
+use crate::list::CustomList;
+
+/// Obfuscated words longer than this are not looked up.
+const MAX_WORD_LEN: usize = 64;
+
+/// Caller-provided map collapsing known variants of spam trigger words into
+/// a single canonical token. Each entry is a canonical word followed by its
+/// variants, all of which are de-leeted before being stored, so `viagra`
+/// alone already matches `v1agra`, `vi@gra` and `VIAGRA`.
+#[derive(Debug, Clone, Default)]
+pub struct SynonymMap {
+    words: AHashMap<String, String>,
+}
+
+impl SynonymMap {
+    /// Returns the canonical token for a raw word as found in the text,
+    /// ignoring surrounding punctuation.
+    pub fn get(&self, word: &str) -> Option<&str> {
+        let word = word
+            .trim_matches(|ch| {
+                matches!(
+                    ch,
+                    ',' | ';' | ':' | '?' | '"' | '(' | ')' | '[' | ']' | '<' | '>'
+                )
+            })
+            .trim_end_matches(['.', '!']);
+        if !word.is_empty() && word.len() <= MAX_WORD_LEN {
+            self.words.get(&deleet(word)).map(|word| word.as_str())
+        } else {
+            None
+        }
+    }
+}
+
+impl CustomList for SynonymMap {
+    fn insert(&mut self, entry: String) {
+        let mut words = entry.split_whitespace();
+        if let Some(canonical) = words.next() {
+            for word in std::iter::once(canonical).chain(words) {
+                self.words.insert(deleet(word), canonical.to_string());
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+/// Undoes common character substitutions (`v1@gra` -> `viagra`) and drops
+/// the separators inserted between letters (`v.i-a_g*r.a`).
+pub fn deleet(word: &str) -> String {
+    word.chars()
+        .filter_map(|ch| match ch {
+            '0' => Some('o'),
+            '1' | '!' => Some('i'),
+            '|' => Some('l'),
+            '3' => Some('e'),
+            '4' | '@' => Some('a'),
+            '5' | '$' => Some('s'),
+            '7' | '+' => Some('t'),
+            '.' | '-' | '_' | '*' | '\'' => None,
+            ch => Some(ch),
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::list::CustomList;
+
+    use super::{deleet, SynonymMap};
+
+    #[test]
+    fn synonym_map() {
+        assert_eq!(deleet("V1@GRA"), "viagra");
+        assert_eq!(deleet("v.i-a_g*r.a"), "viagra");
+        assert_eq!(deleet("ca$!no"), "casino");
+
+        let map =
+            SynonymMap::from_reader(&b"# trigger words\nviagra vi4gra\n\ncasino kasino\n"[..])
+                .unwrap();
+        assert_eq!(map.len(), 3);
+        for word in [
+            "viagra", "VIAGRA", "v1agra", "vi@gra", "v!agra", "(v1agra)", "vi4gra!",
+        ] {
+            assert_eq!(map.get(word), Some("viagra"), "failed for {word:?}");
+        }
+        for word in ["c@sino", "k@sino", "Kasino,"] {
+            assert_eq!(map.get(word), Some("casino"), "failed for {word:?}");
+        }
+        for word in ["vagina", "viagras", "agra", "", "..."] {
+            assert_eq!(map.get(word), None, "failed for {word:?}");
+        }
+    }
+}
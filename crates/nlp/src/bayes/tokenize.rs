@@ -3,10 +3,11 @@ use std::borrow::Cow;
 use utils::suffixlist::PublicSuffix;
 
 use crate::{
+    bayes::synonyms::SynonymMap,
     language::{
         detect::{LanguageDetector, MIN_LANGUAGE_SCORE},
-        stemmer::STEMMER_MAP,
-        stopwords::STOP_WORDS,
+        stemmer::{StemmerExceptions, STEMMER_MAP},
+        stopwords::{CustomStopWords, STOP_WORDS},
         Language,
     },
     tokenizers::{
@@ -20,6 +21,11 @@ pub struct BayesTokenizer<'x, 'y> {
     tokenizer: TypesTokenizer<'x, 'y>,
     stemmer: Stemmer,
     stop_words: Option<&'static phf::Set<&'static str>>,
+    custom_stop_words: Option<&'y CustomStopWords>,
+    stemmer_exceptions: Option<&'y StemmerExceptions>,
+    synonyms: Option<&'y SynonymMap>,
+    keep_case: bool,
+    skip_until: usize,
     tokens: Vec<Cow<'x, str>>,
 }
 
@@ -39,6 +45,12 @@ impl<'x, 'y> BayesTokenizer<'x, 'y> {
             language = Language::English;
         }
 
+        Self::with_language(text, language, suffixes)
+    }
+
+    /// Creates a tokenizer for text already known to be in `language`,
+    /// skipping language detection.
+    pub fn with_language(text: &'x str, language: Language, suffixes: &'y PublicSuffix) -> Self {
         Self {
             text,
             tokenizer: TypesTokenizer::new(text, suffixes),
@@ -50,9 +62,86 @@ impl<'x, 'y> BayesTokenizer<'x, 'y> {
                     .unwrap_or(Stemmer::None),
             },
             stop_words: STOP_WORDS[language as usize],
+            custom_stop_words: None,
+            stemmer_exceptions: None,
+            synonyms: None,
+            keep_case: false,
+            skip_until: 0,
             tokens: vec![],
         }
     }
+
+    pub fn with_stop_words(mut self, stop_words: &'y CustomStopWords) -> Self {
+        self.custom_stop_words = Some(stop_words);
+        self
+    }
+
+    pub fn with_stemmer_exceptions(mut self, exceptions: &'y StemmerExceptions) -> Self {
+        self.stemmer_exceptions = Some(exceptions);
+        self
+    }
+
+    /// Replaces known variants of trigger words with their canonical token.
+    /// Lookups use the whole whitespace-delimited word, so obfuscations that
+    /// the tokenizer would split (`vi@gra`) are still matched.
+    pub fn with_synonyms(mut self, synonyms: &'y SynonymMap) -> Self {
+        self.synonyms = Some(synonyms);
+        self
+    }
+
+    /// Controls whether words keep their original case instead of being
+    /// lowercased, for consumers that fold case themselves such as
+    /// `OsbTokenizer::emit_original_case`. Stems keep the case of the word
+    /// they were cut from.
+    pub fn keep_case(mut self, keep_case: bool) -> Self {
+        self.keep_case = keep_case;
+        self
+    }
+
+    /// Returns the prefix of `original` that lowercases to `word`, or `word`
+    /// itself if case is not kept or `word` is not such a prefix.
+    fn restore_case(&self, original: &'x str, word: Cow<'x, str>) -> Cow<'x, str> {
+        if !self.keep_case {
+            return word;
+        }
+
+        let mut len = 0;
+        let mut word_chars = word.chars().peekable();
+        for ch in original.chars() {
+            let Some(&word_ch) = word_chars.peek() else {
+                break;
+            };
+            let mut lower = ch.to_lowercase();
+            if lower.next() != Some(word_ch) || lower.next().is_some() {
+                return word;
+            }
+            word_chars.next();
+            len += ch.len_utf8();
+        }
+
+        if word_chars.peek().is_none() {
+            original[..len].into()
+        } else {
+            word
+        }
+    }
+
+    fn is_stop_word(&self, word: &str) -> bool {
+        if let Some(custom) = self.custom_stop_words {
+            custom.contains(self.stop_words, word)
+        } else {
+            self.stop_words.map_or(false, |sw| sw.contains(word))
+        }
+    }
+
+    fn synonym(&mut self, from: usize) -> Option<Cow<'x, str>> {
+        let text = self.text;
+        let text = &text[from..];
+        let len = text.find(char::is_whitespace).unwrap_or(text.len());
+        let canonical = self.synonyms?.get(&text[..len])?;
+        self.skip_until = from + len;
+        Some(canonical.to_string().into())
+    }
 }
 
 impl<'x, 'y> Iterator for BayesTokenizer<'x, 'y> {
@@ -65,21 +154,35 @@ impl<'x, 'y> Iterator for BayesTokenizer<'x, 'y> {
 
         loop {
             let token = self.tokenizer.next()?;
+            if token.from < self.skip_until {
+                continue;
+            }
+            if matches!(
+                token.word,
+                TokenType::Alphabetic(_) | TokenType::Alphanumeric(_)
+            ) {
+                if let Some(canonical) = self.synonym(token.from) {
+                    return Some(canonical);
+                }
+            }
 
             let word: Cow<str> = match token.word {
-                TokenType::Alphabetic(word) => {
-                    let word = word.to_lowercase();
-                    if self
-                        .stop_words
-                        .map_or(false, |sw| sw.contains(word.as_str()))
-                    {
+                TokenType::Alphabetic(original) => {
+                    let word = original.to_lowercase();
+                    if self.is_stop_word(&word) {
                         continue;
                     }
+                    if let Some(stem) = self.stemmer_exceptions.and_then(|e| e.get(&word)) {
+                        return Some(self.restore_case(original, stem.to_string().into()));
+                    }
                     match &self.stemmer {
-                        Stemmer::IndoEuropean(stemmer) => match stemmer.stem(&word) {
-                            Cow::Borrowed(_) => word.into(),
-                            Cow::Owned(stemmed_word) => stemmed_word.into(),
-                        },
+                        Stemmer::IndoEuropean(stemmer) => {
+                            let stemmed_word = match stemmer.stem(&word) {
+                                Cow::Borrowed(_) => word,
+                                Cow::Owned(stemmed_word) => stemmed_word,
+                            };
+                            self.restore_case(original, stemmed_word.into())
+                        }
                         Stemmer::Mandarin => {
                             let mut result = JIEBA.cut(&word, false).into_iter();
                             if let Some(stemmed_word) = result.next() {
@@ -104,7 +207,7 @@ impl<'x, 'y> Iterator for BayesTokenizer<'x, 'y> {
                                 continue;
                             }
                         }
-                        Stemmer::None => word.into(),
+                        Stemmer::None => self.restore_case(original, word.into()),
                     }
                 }
 
@@ -123,9 +226,14 @@ impl<'x, 'y> Iterator for BayesTokenizer<'x, 'y> {
                     .map_or(word, |(h, _)| h)
                     .to_lowercase()
                     .into(),
-                TokenType::Alphanumeric(word)
-                | TokenType::Email(word)
-                | TokenType::UrlNoHost(word) => word.to_lowercase().into(),
+                TokenType::Alphanumeric(word) => {
+                    if self.keep_case {
+                        word.into()
+                    } else {
+                        word.to_lowercase().into()
+                    }
+                }
+                TokenType::Email(word) | TokenType::UrlNoHost(word) => word.to_lowercase().into(),
                 TokenType::Other(ch) => {
                     if SYMBOLS.contains(&ch) {
                         (&self.text[token.from..token.to]).into()
@@ -1144,7 +1252,135 @@ mod tests {
 
     use utils::suffixlist::PublicSuffix;
 
-    use crate::bayes::tokenize::BayesTokenizer;
+    use crate::{
+        bayes::{synonyms::SynonymMap, tokenize::BayesTokenizer, TokenHash},
+        language::{stemmer::StemmerExceptions, stopwords::CustomStopWords, Language},
+        list::CustomList,
+        tokenizers::osb::{Gram, OsbTokenizer},
+    };
+
+    #[test]
+    fn custom_stop_words() {
+        let suffixes = PublicSuffix::default();
+        let text = "The quick brown fox jumps over the lazy dog";
+
+        // Merged with the built-in list
+        let stop_words = CustomStopWords::from_entries(&["Quick", "# comment", "lazy"]);
+        assert_eq!(
+            BayesTokenizer::new(text, &suffixes)
+                .with_stop_words(&stop_words)
+                .collect::<Vec<_>>(),
+            vec!["brown", "fox", "jump", "dog"]
+        );
+
+        // Replacing the built-in list
+        let stop_words = CustomStopWords::from_reader(&b"quick\n\n# comment\nlazy\n"[..])
+            .unwrap()
+            .replace_builtin(true);
+        assert_eq!(stop_words.len(), 2);
+        assert_eq!(
+            BayesTokenizer::new(text, &suffixes)
+                .with_stop_words(&stop_words)
+                .collect::<Vec<_>>(),
+            vec!["the", "brown", "fox", "jump", "over", "the", "dog"]
+        );
+
+        // Stemming exceptions
+        let exceptions = StemmerExceptions::from_reader(&b"jumps\nlazy lazy-stem\n"[..]).unwrap();
+        assert_eq!(
+            BayesTokenizer::new(text, &suffixes)
+                .with_stemmer_exceptions(&exceptions)
+                .collect::<Vec<_>>(),
+            vec!["quick", "brown", "fox", "jumps", "lazy-stem", "dog"]
+        );
+    }
+
+    #[test]
+    fn synonyms() {
+        let suffixes = PublicSuffix::default();
+        let text = "Buy v1agra, VIAGRA and vi@gra at the casino or k@sino today";
+        let synonyms = SynonymMap::from_entries(&["viagra vi4gra", "casino kasino"]);
+
+        assert_eq!(
+            BayesTokenizer::with_language(text, Language::English, &suffixes)
+                .with_synonyms(&synonyms)
+                .collect::<Vec<_>>(),
+            vec!["buy", "viagra", "viagra", "viagra", "casino", "casino", "today"]
+        );
+
+        // Without the map, obfuscations remain distinct features
+        let tokens =
+            BayesTokenizer::with_language(text, Language::English, &suffixes).collect::<Vec<_>>();
+        assert_eq!(tokens.first().map(|t| t.as_ref()), Some("buy"));
+        assert!(tokens.iter().any(|t| t == "v1agra"));
+        assert_eq!(tokens.last().map(|t| t.as_ref()), Some("today"));
+    }
+
+    #[test]
+    fn original_case() {
+        let suffixes = PublicSuffix::default();
+        let text = "FREE Money for you, V1AGRA Offers";
+
+        assert_eq!(
+            BayesTokenizer::new(text, &suffixes).collect::<Vec<_>>(),
+            vec!["free", "money", "v1agra", "offer"]
+        );
+        assert_eq!(
+            BayesTokenizer::new(text, &suffixes)
+                .keep_case(true)
+                .collect::<Vec<_>>(),
+            vec!["FREE", "Money", "V1AGRA", "Offer"]
+        );
+
+        // Original case grams follow their lowercased version
+        let grams =
+            OsbTokenizer::<_, String>::new(BayesTokenizer::new(text, &suffixes).keep_case(true), 3)
+                .emit_original_case(true)
+                .map(|t| (t.inner, t.idx))
+                .collect::<Vec<_>>();
+        assert_eq!(
+            grams
+                .iter()
+                .map(|(gram, idx)| (gram.as_str(), *idx))
+                .collect::<Vec<_>>(),
+            [
+                ("free", 0),
+                ("FREE", 0),
+                ("free money", 1),
+                ("FREE Money", 1),
+                ("free v1agra", 2),
+                ("FREE V1AGRA", 2),
+                ("money", 0),
+                ("Money", 0),
+                ("money v1agra", 1),
+                ("Money V1AGRA", 1),
+                ("money offer", 2),
+                ("Money Offer", 2),
+                ("v1agra", 0),
+                ("V1AGRA", 0),
+                ("v1agra offer", 1),
+                ("V1AGRA Offer", 1),
+                ("offer", 0),
+                ("Offer", 0),
+            ]
+        );
+
+        // The window is unchanged by the original case grams
+        assert_eq!(
+            grams
+                .into_iter()
+                .filter(|(gram, _)| !gram.chars().any(char::is_uppercase))
+                .collect::<Vec<_>>(),
+            OsbTokenizer::<_, String>::new(BayesTokenizer::new(text, &suffixes), 3)
+                .map(|t| (t.inner, t.idx))
+                .collect::<Vec<_>>()
+        );
+
+        // Both grams are hashed as distinct features
+        let lower = TokenHash::from(Gram::Uni { t1: "free" });
+        let upper = TokenHash::from(Gram::Uni { t1: "FREE" });
+        assert_ne!(lower, upper);
+    }
 
     #[test]
     fn bayes_tokenizer() {
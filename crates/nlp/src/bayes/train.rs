@@ -23,7 +23,7 @@
 
 use crate::tokenizers::osb::OsbToken;
 
-use super::{BayesModel, TokenHash};
+use super::{BayesModel, CountDelta, TokenHash};
 
 impl BayesModel {
     pub fn train<T>(&mut self, tokens: T, is_spam: bool)
@@ -46,6 +46,36 @@ impl BayesModel {
         }
     }
 
+    /// Trains a message capping its contribution to `max_features` grams.
+    /// Larger messages are sampled down deterministically by keeping the
+    /// grams with the lowest hashes, so the same message always retains the
+    /// same features.
+    pub fn train_capped<T>(&mut self, tokens: T, is_spam: bool, max_features: usize)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        let mut tokens = tokens.into_iter().collect::<Vec<_>>();
+        if tokens.len() > max_features {
+            if max_features > 0 {
+                tokens.select_nth_unstable_by_key(max_features - 1, |t| (t.inner.h1, t.inner.h2));
+            }
+            tokens.truncate(max_features);
+        }
+        self.train(tokens, is_spam);
+    }
+
+    /// Applies a batch of weight changes, such as those accumulated by a
+    /// write-behind training queue, in a single pass. Weights saturate at
+    /// zero and `u32::MAX` and the learn counters are left untouched.
+    pub fn apply_deltas(&mut self, deltas: &[(TokenHash, CountDelta)]) {
+        self.weights.reserve(deltas.len());
+        for (token, delta) in deltas {
+            let hs = self.weights.entry(*token).or_default();
+            hs.spam = hs.spam.saturating_add_signed(delta.spam);
+            hs.ham = hs.ham.saturating_add_signed(delta.ham);
+        }
+    }
+
     pub fn untrain<T>(&mut self, tokens: T, is_spam: bool)
     where
         T: IntoIterator<Item = OsbToken<TokenHash>>,
@@ -66,3 +96,139 @@ impl BayesModel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::{
+        bayes::{tokenize::BayesTokenizer, BayesModel, CountDelta, TokenHash, Weights},
+        tokenizers::osb::{OsbToken, OsbTokenizer},
+    };
+
+    #[test]
+    fn train_capped() {
+        let suffixes = PublicSuffix::default();
+        let huge = (0..1000)
+            .map(|n| format!("word{n}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut model = BayesModel::default();
+        model.train_capped(
+            OsbTokenizer::new(BayesTokenizer::new(&huge, &suffixes), 5),
+            true,
+            100,
+        );
+        assert_eq!(model.spam_learns, 1);
+        assert_eq!(
+            model.weights.values().map(|w| w.spam).sum::<u32>(),
+            100,
+            "the huge message must be capped"
+        );
+
+        // Sampling is deterministic
+        let mut other = BayesModel::default();
+        other.train_capped(
+            OsbTokenizer::new(BayesTokenizer::new(&huge, &suffixes), 5),
+            true,
+            100,
+        );
+        assert_eq!(model.weights, other.weights);
+
+        // Normal messages are unaffected by the cap
+        let mut capped = BayesModel::default();
+        let mut uncapped = BayesModel::default();
+        capped.train_capped(
+            OsbTokenizer::new(BayesTokenizer::new("cheap pills", &suffixes), 2),
+            true,
+            100,
+        );
+        uncapped.train(
+            OsbTokenizer::new(BayesTokenizer::new("cheap pills", &suffixes), 2),
+            true,
+        );
+        assert_eq!(capped.weights.len(), 3);
+        assert_eq!(capped.weights, uncapped.weights);
+    }
+
+    #[test]
+    fn apply_deltas() {
+        let token = |h1| TokenHash { h1, h2: 0 };
+        let grams = |hashes: &[u64]| {
+            hashes
+                .iter()
+                .map(|&h1| OsbToken {
+                    inner: token(h1),
+                    idx: 0,
+                })
+                .collect::<Vec<_>>()
+        };
+        let base = || {
+            let mut model = BayesModel::default();
+            model.train(grams(&[1, 2]), true);
+            model.train(grams(&[2, 3]), false);
+            model
+        };
+
+        // The same updates through train/untrain and as a batch of deltas
+        let mut expected = base();
+        expected.train(grams(&[1, 3]), true);
+        expected.train(grams(&[1]), false);
+        expected.untrain(grams(&[1, 2]), true);
+
+        let mut batched = base();
+        batched.apply_deltas(&[
+            (token(1), CountDelta { spam: 1, ham: 0 }),
+            (token(3), CountDelta { spam: 1, ham: 0 }),
+            (token(1), CountDelta { spam: 0, ham: 1 }),
+            (token(1), CountDelta { spam: -1, ham: 0 }),
+            (token(2), CountDelta { spam: -1, ham: 0 }),
+        ]);
+        assert_eq!(batched.weights, expected.weights);
+        for (hash, weights) in [
+            (1, Weights { spam: 1, ham: 1 }),
+            (2, Weights { spam: 0, ham: 1 }),
+            (3, Weights { spam: 1, ham: 1 }),
+        ] {
+            assert_eq!(batched.weights[&token(hash)], weights);
+        }
+
+        // Learn counters are left to the caller
+        assert_eq!((batched.spam_learns, batched.ham_learns), (1, 1));
+
+        // Counts saturate instead of wrapping
+        let new_token = token(4);
+        let mut model = BayesModel::default();
+        model.apply_deltas(&[
+            (
+                new_token,
+                CountDelta {
+                    spam: -5,
+                    ham: i32::MAX,
+                },
+            ),
+            (
+                new_token,
+                CountDelta {
+                    spam: 0,
+                    ham: i32::MAX,
+                },
+            ),
+            (
+                new_token,
+                CountDelta {
+                    spam: 0,
+                    ham: i32::MAX,
+                },
+            ),
+        ]);
+        assert_eq!(
+            model.weights[&new_token],
+            Weights {
+                spam: 0,
+                ham: u32::MAX
+            }
+        );
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+/// Weight of an OSB feature given its skip distance `idx`, decaying
+/// exponentially as `e^(-decay * idx)` so that adjacent pairs and unigrams
+/// (`idx == 0`) weigh `1.0` and distant skip-bigrams progressively less.
+/// Negative decays are treated as `0.0`, which weighs all features equally.
+pub fn osb_weight(idx: usize, decay: f64) -> f64 {
+    (-decay.max(0.0) * idx as f64).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::osb_weight;
+
+    #[test]
+    fn osb_weights() {
+        for decay in [0.1, 0.5, 1.0, 2.0] {
+            let weights = (0..8).map(|idx| osb_weight(idx, decay)).collect::<Vec<_>>();
+
+            assert_eq!(weights[0], 1.0);
+            assert!(weights.iter().all(|weight| *weight <= weights[0]));
+            assert!(
+                weights.windows(2).all(|pair| pair[1] < pair[0]),
+                "{weights:?}"
+            );
+        }
+
+        assert_eq!(osb_weight(5, 0.0), 1.0);
+        assert_eq!(osb_weight(5, -1.0), 1.0);
+    }
+}
@@ -28,6 +28,14 @@ use super::Language;
 
 pub const MIN_LANGUAGE_SCORE: f64 = 0.5;
 
+/// Minimum number of alphabetic characters required before attempting to
+/// guess the language of a text.
+pub const MIN_DETECT_CHARS: usize = 12;
+
+/// Code returned by `LanguageDetector::detect_iso_639` when no language
+/// could be detected with enough confidence.
+pub const UNKNOWN_ISO_639: &str = "unknown";
+
 #[derive(Debug)]
 struct WeightedAverage {
     weight: usize,
@@ -87,6 +95,24 @@ impl LanguageDetector {
             .map(|(l, _)| *l)
     }
 
+    /// Returns the ISO 639-1 code of the language the text is written in,
+    /// or `UNKNOWN_ISO_639` when the text is too short or the detection
+    /// confidence is below `min_score`.
+    pub fn detect_iso_639(text: &str, min_score: f64) -> &'static str {
+        if text.chars().filter(|ch| ch.is_alphabetic()).count() < MIN_DETECT_CHARS {
+            return UNKNOWN_ISO_639;
+        }
+        LanguageDetector::detect_single(text)
+            .and_then(|(language, confidence)| {
+                if confidence >= min_score {
+                    language.to_iso_639()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(UNKNOWN_ISO_639)
+    }
+
     pub fn detect_single(text: &str) -> Option<(Language, f64)> {
         detect(text).map(|info| {
             (
@@ -223,6 +249,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn detect_iso_639() {
+        for (text, expected) in [
+            (
+                "The project deadline has moved to next week, please review the attached report before the meeting.",
+                "en",
+            ),
+            (
+                "Sehr geehrte Damen und Herren, anbei finden Sie die Rechnung für den vergangenen Monat.",
+                "de",
+            ),
+            ("ok", UNKNOWN_ISO_639),
+            ("ok sounds good", UNKNOWN_ISO_639),
+        ] {
+            assert_eq!(
+                LanguageDetector::detect_iso_639(text, MIN_LANGUAGE_SCORE),
+                expected,
+                "{text:?}"
+            );
+        }
+    }
+
     #[test]
     fn weighted_language() {
         let mut detector = LanguageDetector::new();
@@ -140,6 +140,83 @@ impl Language {
             .get(code.split_once('-').map(|c| c.0).unwrap_or(code))
             .copied()
     }
+
+    /// Returns the ISO 639-1 code of the language, or `None` for
+    /// `Language::Unknown` and `Language::None`.
+    pub fn to_iso_639(&self) -> Option<&'static str> {
+        Some(match self {
+            Language::Esperanto => "eo",
+            Language::English => "en",
+            Language::Russian => "ru",
+            Language::Mandarin => "zh",
+            Language::Spanish => "es",
+            Language::Portuguese => "pt",
+            Language::Italian => "it",
+            Language::Bengali => "bn",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Ukrainian => "uk",
+            Language::Georgian => "ka",
+            Language::Arabic => "ar",
+            Language::Hindi => "hi",
+            Language::Japanese => "ja",
+            Language::Hebrew => "he",
+            Language::Yiddish => "yi",
+            Language::Polish => "pl",
+            Language::Amharic => "am",
+            Language::Javanese => "jv",
+            Language::Korean => "ko",
+            Language::Bokmal => "nb",
+            Language::Danish => "da",
+            Language::Swedish => "sv",
+            Language::Finnish => "fi",
+            Language::Turkish => "tr",
+            Language::Dutch => "nl",
+            Language::Hungarian => "hu",
+            Language::Czech => "cs",
+            Language::Greek => "el",
+            Language::Bulgarian => "bg",
+            Language::Belarusian => "be",
+            Language::Marathi => "mr",
+            Language::Kannada => "kn",
+            Language::Romanian => "ro",
+            Language::Slovene => "sl",
+            Language::Croatian => "hr",
+            Language::Serbian => "sr",
+            Language::Macedonian => "mk",
+            Language::Lithuanian => "lt",
+            Language::Latvian => "lv",
+            Language::Estonian => "et",
+            Language::Tamil => "ta",
+            Language::Vietnamese => "vi",
+            Language::Urdu => "ur",
+            Language::Thai => "th",
+            Language::Gujarati => "gu",
+            Language::Uzbek => "uz",
+            Language::Punjabi => "pa",
+            Language::Azerbaijani => "az",
+            Language::Indonesian => "id",
+            Language::Telugu => "te",
+            Language::Persian => "fa",
+            Language::Malayalam => "ml",
+            Language::Oriya => "or",
+            Language::Burmese => "my",
+            Language::Nepali => "ne",
+            Language::Sinhalese => "si",
+            Language::Khmer => "km",
+            Language::Turkmen => "tk",
+            Language::Akan => "ak",
+            Language::Zulu => "zu",
+            Language::Shona => "sn",
+            Language::Afrikaans => "af",
+            Language::Latin => "la",
+            Language::Slovak => "sk",
+            Language::Catalan => "ca",
+            Language::Tagalog => "tl",
+            Language::Armenian => "hy",
+            Language::Unknown | Language::None => return None,
+        })
+    }
 }
 
 impl Language {
@@ -23,8 +23,11 @@
 
 use std::borrow::Cow;
 
+use ahash::AHashMap;
 use rust_stemmers::Algorithm;
 
+use crate::list::CustomList;
+
 use super::{Language, LanguageTokenizer};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -70,6 +73,34 @@ impl<'x> Iterator for Stemmer<'x> {
     }
 }
 
+/// Caller-provided stemming exceptions, consulted before the algorithmic
+/// stemmer. An entry is either a single word, which is kept unstemmed, or a
+/// `word stem` pair mapping the word to an explicit stem.
+#[derive(Debug, Clone, Default)]
+pub struct StemmerExceptions {
+    stems: AHashMap<String, String>,
+}
+
+impl StemmerExceptions {
+    pub fn get(&self, word: &str) -> Option<&str> {
+        self.stems.get(word).map(|stem| stem.as_str())
+    }
+}
+
+impl CustomList for StemmerExceptions {
+    fn insert(&mut self, entry: String) {
+        let (word, stem) = match entry.split_once(char::is_whitespace) {
+            Some((word, stem)) => (word.to_string(), stem.trim().to_string()),
+            None => (entry.clone(), entry),
+        };
+        self.stems.insert(word, stem);
+    }
+
+    fn len(&self) -> usize {
+        self.stems.len()
+    }
+}
+
 pub static STEMMER_MAP: &[Option<Algorithm>] = &[
     None,                        // Esperanto = 0,
     Some(Algorithm::English),    // English = 1,
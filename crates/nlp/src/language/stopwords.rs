@@ -21,7 +21,41 @@
  * for more details.
 */
 
+use ahash::AHashSet;
 use phf::{phf_set, Set};
+
+use crate::list::CustomList;
+
+/// Caller-provided stop words, either merged with or replacing the
+/// built-in list for the detected language.
+#[derive(Debug, Clone, Default)]
+pub struct CustomStopWords {
+    words: AHashSet<String>,
+    replace_builtin: bool,
+}
+
+impl CustomStopWords {
+    pub fn replace_builtin(mut self, replace_builtin: bool) -> Self {
+        self.replace_builtin = replace_builtin;
+        self
+    }
+
+    pub fn contains(&self, builtin: Option<&Set<&'static str>>, word: &str) -> bool {
+        self.words.contains(word)
+            || (!self.replace_builtin && builtin.map_or(false, |sw| sw.contains(word)))
+    }
+}
+
+impl CustomList for CustomStopWords {
+    fn insert(&mut self, entry: String) {
+        self.words.insert(entry);
+    }
+
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
 pub static STOP_WORDS: &[Option<&Set<&'static str>>] = &[
     None,               // Esperanto = 0,
     Some(&ENGLISH),     // English = 1,
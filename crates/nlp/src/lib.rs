@@ -1,5 +1,7 @@
 pub mod bayes;
 pub mod language;
+pub mod list;
+pub mod state;
 pub mod tokenizers;
 
 #[cfg(test)]
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::io::Read;
+
+/// A word list provided by the caller, read one entry per line. Entries are
+/// trimmed and lowercased, empty lines and `#` comments are skipped.
+pub trait CustomList: Default {
+    /// Adds a single parsed entry to the list.
+    fn insert(&mut self, entry: String);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn from_entries(entries: &[&str]) -> Self {
+        let mut list = Self::default();
+        for entry in entries.iter().filter_map(|entry| parse_entry(entry)) {
+            list.insert(entry);
+        }
+        list
+    }
+
+    fn from_reader(mut reader: impl Read) -> std::io::Result<Self> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+
+        let mut list = Self::default();
+        for entry in contents.lines().filter_map(parse_entry) {
+            list.insert(entry);
+        }
+        Ok(list)
+    }
+}
+
+fn parse_entry(line: &str) -> Option<String> {
+    let line = line.trim();
+    if !line.is_empty() && !line.starts_with('#') {
+        Some(line.to_lowercase())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+use utils::suffixlist::PublicSuffix;
+
+use crate::{
+    bayes::{
+        frequency::DocumentFrequencyCounter, tokenize::BayesTokenizer, BayesClassifier, BayesModel,
+        TokenHash,
+    },
+    tokenizers::osb::OsbTokenizer,
+};
+
+const STATE_FORMAT_VERSION: u8 = 1;
+
+/// Checkpoint of the whole NLP subsystem, serialized as a single blob
+/// composed of a version byte, a CRC32 of the payload and the payload.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NlpState {
+    pub tokenizer: TokenizerConfig,
+    pub classifier: BayesClassifier,
+    pub model: BayesModel,
+    pub document_frequencies: DocumentFrequencyCounter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerConfig {
+    pub window_size: usize,
+    pub flush_partial: bool,
+    pub emit_original_case: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            window_size: 5,
+            flush_partial: true,
+            emit_original_case: false,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    pub fn tokenize<'x, 'y>(
+        &self,
+        text: &'x str,
+        suffixes: &'y PublicSuffix,
+    ) -> OsbTokenizer<'x, BayesTokenizer<'x, 'y>, TokenHash> {
+        OsbTokenizer::new(
+            BayesTokenizer::new(text, suffixes).keep_case(self.emit_original_case),
+            self.window_size,
+        )
+        .flush_partial(self.flush_partial)
+        .emit_original_case(self.emit_original_case)
+    }
+}
+
+impl NlpState {
+    /// Writes the state in a single call once it has been fully serialized,
+    /// so a failure while serializing leaves the writer untouched.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        let payload = bincode::serialize(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut bytes = Vec::with_capacity(payload.len() + 5);
+        bytes.push(STATE_FORMAT_VERSION);
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Reads and verifies a complete state, failing with
+    /// `io::ErrorKind::InvalidData` on version or checksum mismatches.
+    pub fn load(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        match bytes.split_first() {
+            Some((&STATE_FORMAT_VERSION, bytes)) if bytes.len() >= 4 => {
+                let (crc, payload) = bytes.split_at(4);
+                if crc32fast::hash(payload).to_le_bytes() == crc {
+                    bincode::deserialize(payload)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "NLP state checksum mismatch",
+                    ))
+                }
+            }
+            Some((&STATE_FORMAT_VERSION, _)) | None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated NLP state",
+            )),
+            Some((version, _)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported NLP state version {version}"),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use utils::suffixlist::PublicSuffix;
+
+    use crate::bayes::{BayesClassifier, BayesModel};
+
+    use super::{NlpState, TokenizerConfig};
+
+    fn sample_state() -> NlpState {
+        let suffixes = PublicSuffix::default();
+        let tokenizer = TokenizerConfig {
+            window_size: 2,
+            flush_partial: false,
+            emit_original_case: true,
+        };
+        let mut model = BayesModel::default();
+        for (text, is_spam) in [("Cheap pills", true), ("team meeting", false)] {
+            model.train(tokenizer.tokenize(text, &suffixes), is_spam);
+        }
+        let mut state = NlpState {
+            tokenizer,
+            classifier: BayesClassifier {
+                min_tokens: 1,
+                min_learns: 1,
+                ..BayesClassifier::new()
+            },
+            model,
+            ..Default::default()
+        };
+        state
+            .classifier
+            .calibrate(&[(0.2, false), (0.4, false), (0.6, true), (0.9, true)]);
+        for text in ["Cheap pills", "team meeting"] {
+            state
+                .document_frequencies
+                .add_document(tokenizer.tokenize(text, &suffixes));
+        }
+        state
+    }
+
+    #[test]
+    fn nlp_state_round_trip() {
+        let state = sample_state();
+        let mut bytes = Vec::new();
+        state.save(&mut bytes).unwrap();
+
+        let restored = NlpState::load(&bytes[..]).unwrap();
+        assert_eq!(restored.tokenizer, state.tokenizer);
+        assert_eq!(
+            restored.classifier.calibration,
+            state.classifier.calibration
+        );
+        assert!(restored.classifier.calibration.is_some());
+        assert_eq!(
+            restored.classifier.min_token_hits,
+            state.classifier.min_token_hits
+        );
+        assert_eq!(restored.model.weights, state.model.weights);
+        assert_eq!(
+            (restored.model.spam_learns, restored.model.ham_learns),
+            (1, 1)
+        );
+        assert_eq!(restored.document_frequencies, state.document_frequencies);
+    }
+
+    #[test]
+    fn nlp_state_rejects_invalid() {
+        let mut bytes = Vec::new();
+        sample_state().save(&mut bytes).unwrap();
+
+        let mut bad_version = bytes.clone();
+        bad_version[0] += 1;
+        assert_eq!(
+            NlpState::load(&bad_version[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert_eq!(
+            NlpState::load(&corrupted[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        assert_eq!(
+            NlpState::load(&bytes[..3]).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert!(NlpState::load(&[][..]).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{ArrayBuilder, StringBuilder, UInt32Builder, UInt64Builder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use super::osb::OsbToken;
+
+/// Accumulates OSB token streams into an Arrow record batch with one row
+/// per gram and the columns `doc_id: UInt64`, `gram: Utf8` and `idx: UInt32`.
+pub struct ArrowExporter {
+    doc_ids: UInt64Builder,
+    grams: StringBuilder,
+    idxs: UInt32Builder,
+}
+
+impl ArrowExporter {
+    pub fn new() -> Self {
+        ArrowExporter {
+            doc_ids: UInt64Builder::new(),
+            grams: StringBuilder::new(),
+            idxs: UInt32Builder::new(),
+        }
+    }
+
+    pub fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("doc_id", DataType::UInt64, false),
+            Field::new("gram", DataType::Utf8, false),
+            Field::new("idx", DataType::UInt32, false),
+        ]))
+    }
+
+    pub fn append<T, I>(&mut self, doc_id: u64, tokens: I)
+    where
+        T: AsRef<str>,
+        I: IntoIterator<Item = OsbToken<T>>,
+    {
+        for token in tokens {
+            self.doc_ids.append_value(doc_id);
+            self.grams.append_value(token.inner.as_ref());
+            self.idxs.append_value(token.idx as u32);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.doc_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc_ids.len() == 0
+    }
+
+    /// Builds the record batch from the appended rows and resets the exporter.
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(self.doc_ids.finish()) as ArrayRef,
+                Arc::new(self.grams.finish()) as ArrayRef,
+                Arc::new(self.idxs.finish()) as ArrayRef,
+            ],
+        )
+    }
+}
+
+impl Default for ArrowExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use arrow_array::{Array, StringArray, UInt32Array, UInt64Array};
+
+    use crate::tokenizers::osb::{OsbToken, OsbTokenizer};
+
+    use super::ArrowExporter;
+
+    #[test]
+    fn arrow_record_batch() {
+        let mut exporter = ArrowExporter::new();
+        for (doc_id, text) in [(1, "buy cheap pills"), (2, "hello world")] {
+            exporter.append(
+                doc_id,
+                OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 2),
+            );
+        }
+        assert_eq!(exporter.len(), 8);
+
+        let batch = exporter.finish().unwrap();
+        assert_eq!(batch.num_rows(), 8);
+        assert!(exporter.is_empty());
+
+        let doc_ids = batch
+            .column_by_name("doc_id")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        let grams = batch
+            .column_by_name("gram")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let idxs = batch
+            .column_by_name("idx")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .unwrap();
+
+        assert_eq!(
+            (0..batch.num_rows())
+                .map(|row| OsbToken {
+                    inner: (doc_ids.value(row), grams.value(row).to_string()),
+                    idx: idxs.value(row) as usize,
+                })
+                .collect::<Vec<_>>(),
+            [
+                (1, "buy", 0),
+                (1, "buy cheap", 1),
+                (1, "cheap", 0),
+                (1, "cheap pills", 1),
+                (1, "pills", 0),
+                (2, "hello", 0),
+                (2, "hello world", 1),
+                (2, "world", 0),
+            ]
+            .into_iter()
+            .map(|(doc_id, gram, idx)| OsbToken {
+                inner: (doc_id, gram.to_string()),
+                idx,
+            })
+            .collect::<Vec<_>>()
+        );
+        assert_eq!(doc_ids.null_count(), 0);
+    }
+}
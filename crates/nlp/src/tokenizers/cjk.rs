@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use unicode_script::{Script, UnicodeScript};
+
+/// Splits text at script boundaries for the [`OsbTokenizer`](super::osb::OsbTokenizer),
+/// breaking runs of Han, Hiragana and Katakana into overlapping character
+/// bigrams (`垃圾邮件` into `垃圾`, `圾邮` and `邮件`) and keeping runs of
+/// other letters and digits whole. Combining marks stay with the character
+/// they follow, and everything else separates tokens. A CJK run of a single
+/// character is emitted as is.
+pub struct CjkTokenizer<'x> {
+    text: &'x str,
+    pos: usize,
+    clusters: Vec<usize>,
+    cluster_idx: usize,
+    bigrams: usize,
+}
+
+impl<'x> CjkTokenizer<'x> {
+    pub fn new(text: &'x str) -> Self {
+        CjkTokenizer {
+            text,
+            pos: 0,
+            clusters: Vec::new(),
+            cluster_idx: 0,
+            bigrams: 0,
+        }
+    }
+}
+
+impl<'x> Iterator for CjkTokenizer<'x> {
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cluster_idx < self.bigrams {
+            let start = self.clusters[self.cluster_idx];
+            let end = self.clusters[(self.cluster_idx + 2).min(self.clusters.len() - 1)];
+            self.cluster_idx += 1;
+            return Some(Cow::Borrowed(&self.text[start..end]));
+        }
+
+        let offset = self.pos;
+        let mut chars = self.text[offset..]
+            .char_indices()
+            .map(|(pos, ch)| (offset + pos, ch))
+            .skip_while(|(_, ch)| !is_cjk(*ch) && !ch.is_alphanumeric());
+        let (start, ch) = chars.next()?;
+        let mut end = self.text.len();
+
+        if is_cjk(ch) {
+            self.clusters.clear();
+            self.clusters.push(start);
+            for (pos, ch) in chars {
+                if is_cjk(ch) || (ch.script() == Script::Common && ch.is_alphabetic()) {
+                    self.clusters.push(pos);
+                } else if ch.script() != Script::Inherited {
+                    end = pos;
+                    break;
+                }
+            }
+            self.clusters.push(end);
+            self.pos = end;
+            self.cluster_idx = 0;
+            self.bigrams = (self.clusters.len() - 2).max(1);
+            self.next()
+        } else {
+            for (pos, ch) in chars {
+                if is_cjk(ch) || !(ch.is_alphanumeric() || ch.script() == Script::Inherited) {
+                    end = pos;
+                    break;
+                }
+            }
+            self.pos = end;
+            Some(Cow::Borrowed(&self.text[start..end]))
+        }
+    }
+}
+
+fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch.script(),
+        Script::Han | Script::Hiragana | Script::Katakana
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::tokenizers::osb::OsbTokenizer;
+
+    use super::CjkTokenizer;
+
+    #[test]
+    fn cjk_tokenizer() {
+        for (text, expected) in [
+            ("垃圾邮件test", vec!["垃圾", "圾邮", "邮件", "test"]),
+            (
+                "免费 viagra,便宜!  now",
+                vec!["免费", "viagra", "便宜", "now"],
+            ),
+            ("我", vec!["我"]),
+            ("セール中", vec!["セー", "ール", "ル中"]),
+            ("か\u{3099}き", vec!["か\u{3099}き"]),
+            ("cafe\u{0301} 2024年", vec!["cafe\u{0301}", "2024", "年"]),
+            ("", vec![]),
+            (" !? ", vec![]),
+        ] {
+            assert_eq!(
+                CjkTokenizer::new(text).collect::<Vec<_>>(),
+                expected,
+                "failed for {text:?}"
+            );
+        }
+
+        // Borrowed tokens feed the OSB tokenizer directly
+        assert_eq!(
+            OsbTokenizer::<_, String>::new(CjkTokenizer::new("垃圾邮件test"), 2)
+                .map(|t| t.inner)
+                .collect::<Vec<_>>(),
+            [
+                "垃圾",
+                "垃圾 圾邮",
+                "圾邮",
+                "圾邮 邮件",
+                "邮件",
+                "邮件 test",
+                "test"
+            ]
+        );
+        assert!(CjkTokenizer::new("垃圾邮件test").all(|t| matches!(t, Cow::Borrowed(_))));
+    }
+}
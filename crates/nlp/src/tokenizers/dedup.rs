@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::HashMap, hash::Hash};
+
+use super::osb::OsbToken;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountedOsbToken<T> {
+    pub inner: T,
+    pub idx: usize,
+    pub count: u32,
+}
+
+/// Collapses repeated `(inner, idx)` pairs from an
+/// [`OsbTokenizer`](super::osb::OsbTokenizer) into a single token carrying
+/// the number of occurrences, so a gram repeated throughout a message is
+/// only counted once by the classifier. Tokens are emitted in first-seen
+/// order. The whole input is consumed on construction, since a count is only
+/// final once the stream is exhausted.
+pub struct OsbDedup<T> {
+    tokens: std::vec::IntoIter<CountedOsbToken<T>>,
+}
+
+impl<T> OsbDedup<T>
+where
+    T: Hash + Eq + Clone,
+{
+    pub fn new(iter: impl IntoIterator<Item = OsbToken<T>>) -> Self {
+        let mut positions: HashMap<OsbToken<T>, usize> = HashMap::new();
+        let mut tokens: Vec<CountedOsbToken<T>> = Vec::new();
+
+        for token in iter {
+            if let Some(&pos) = positions.get(&token) {
+                tokens[pos].count = tokens[pos].count.saturating_add(1);
+            } else {
+                tokens.push(CountedOsbToken {
+                    inner: token.inner.clone(),
+                    idx: token.idx,
+                    count: 1,
+                });
+                positions.insert(token, tokens.len() - 1);
+            }
+        }
+
+        OsbDedup {
+            tokens: tokens.into_iter(),
+        }
+    }
+}
+
+impl<T> Iterator for OsbDedup<T> {
+    type Item = CountedOsbToken<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokens.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.tokens.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for OsbDedup<T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::tokenizers::osb::OsbTokenizer;
+
+    use super::OsbDedup;
+
+    #[test]
+    fn osb_dedup() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat";
+        let tokens =
+            OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5)
+                .collect::<Vec<_>>();
+        let counted = OsbDedup::new(tokens.clone()).collect::<Vec<_>>();
+
+        // Repeated grams collapse into a single entry
+        for (gram, idx, count) in [
+            ("the", 0, 2),
+            ("lazy", 0, 2),
+            ("the lazy", 1, 2),
+            ("The", 0, 1),
+            ("dog the", 2, 1),
+            ("cat", 0, 1),
+        ] {
+            let matches = counted
+                .iter()
+                .filter(|t| t.inner == gram && t.idx == idx)
+                .collect::<Vec<_>>();
+            assert_eq!(matches.len(), 1, "failed for {gram:?}");
+            assert_eq!(matches[0].count, count, "failed for {gram:?}");
+        }
+
+        // The same gram at a different distance stays a separate entry
+        let counted = OsbDedup::new(OsbTokenizer::<_, String>::new(
+            "a b x a b".split_ascii_whitespace().map(Cow::from),
+            5,
+        ))
+        .map(|t| (t.inner, t.idx, t.count))
+        .filter(|(gram, _, _)| gram == "a b")
+        .collect::<Vec<_>>();
+        assert_eq!(
+            counted,
+            vec![("a b".to_string(), 1, 2), ("a b".to_string(), 4, 1)]
+        );
+
+        // First-seen order is preserved and no occurrence is lost
+        let counted = OsbDedup::new(tokens.clone()).collect::<Vec<_>>();
+        let mut first_seen = Vec::new();
+        for token in &tokens {
+            if !first_seen.contains(token) {
+                first_seen.push(token.clone());
+            }
+        }
+        assert_eq!(
+            counted
+                .iter()
+                .map(|t| (t.inner.as_str(), t.idx))
+                .collect::<Vec<_>>(),
+            first_seen
+                .iter()
+                .map(|t| (t.inner.as_str(), t.idx))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            counted.iter().map(|t| t.count as usize).sum::<usize>(),
+            tokens.len()
+        );
+    }
+}
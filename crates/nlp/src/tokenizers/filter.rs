@@ -0,0 +1,190 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, collections::HashSet};
+
+/// Drops the tokens present in a stop word set before they reach the
+/// [`OsbTokenizer`](super::osb::OsbTokenizer).
+///
+/// The window collapses over removed tokens rather than leaving a gap: in
+/// `buy the pills` with `the` removed, `buy` and `pills` become adjacent and
+/// form a bigram with `idx = 1`. Matching is exact, so tokens should be
+/// normalized to the same case as the stop words beforehand.
+pub struct StopwordFilter<'s, I> {
+    iter: I,
+    stop_words: &'s HashSet<Cow<'s, str>>,
+}
+
+impl<'s, I> StopwordFilter<'s, I> {
+    pub fn new(iter: I, stop_words: &'s HashSet<Cow<'s, str>>) -> Self {
+        StopwordFilter { iter, stop_words }
+    }
+}
+
+impl<'x, 's, I> Iterator for StopwordFilter<'s, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stop_words = self.stop_words;
+        self.iter
+            .by_ref()
+            .find(|token| !stop_words.contains(&**token))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Drops the tokens shorter than `min_len` Unicode scalar values, so that
+/// `né` counts as two characters and `日` as one, regardless of their length
+/// in bytes. As with [`StopwordFilter`], the window collapses over removed
+/// tokens.
+pub struct MinLenFilter<I> {
+    iter: I,
+    min_len: usize,
+}
+
+impl<I> MinLenFilter<I> {
+    pub fn new(iter: I, min_len: usize) -> Self {
+        MinLenFilter { iter, min_len }
+    }
+}
+
+impl<'x, I> Iterator for MinLenFilter<I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let min_len = self.min_len;
+        self.iter.by_ref().find(|token| {
+            // A token has at most as many characters as bytes
+            token.len() >= min_len && (min_len == 0 || token.chars().nth(min_len - 1).is_some())
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashSet};
+
+    use crate::tokenizers::osb::OsbTokenizer;
+
+    use super::{MinLenFilter, StopwordFilter};
+
+    #[test]
+    fn stopword_filter() {
+        let stop_words = HashSet::from([Cow::from("the"), Cow::from("a")]);
+        let text = "the quick fox saw a dog";
+
+        assert_eq!(
+            StopwordFilter::new(text.split_ascii_whitespace().map(Cow::from), &stop_words)
+                .collect::<Vec<_>>(),
+            vec!["quick", "fox", "saw", "dog"]
+        );
+
+        // The window collapses over the removed tokens
+        assert_eq!(
+            OsbTokenizer::<_, String>::new(
+                StopwordFilter::new(text.split_ascii_whitespace().map(Cow::from), &stop_words),
+                3,
+            )
+            .map(|t| (t.inner, t.idx))
+            .collect::<Vec<_>>(),
+            [
+                ("quick", 0),
+                ("quick fox", 1),
+                ("quick saw", 2),
+                ("fox", 0),
+                ("fox saw", 1),
+                ("fox dog", 2),
+                ("saw", 0),
+                ("saw dog", 1),
+                ("dog", 0),
+            ]
+            .into_iter()
+            .map(|(gram, idx)| (gram.to_string(), idx))
+            .collect::<Vec<_>>()
+        );
+
+        // Nothing left to tokenize
+        assert_eq!(
+            StopwordFilter::new(["the", "a"].into_iter().map(Cow::from), &stop_words).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn min_len_filter() {
+        let text = "I saw a big dog in the park";
+
+        assert_eq!(
+            MinLenFilter::new(text.split_ascii_whitespace().map(Cow::from), 3).collect::<Vec<_>>(),
+            vec!["saw", "big", "dog", "the", "park"]
+        );
+
+        // The window collapses over the removed tokens
+        assert_eq!(
+            OsbTokenizer::<_, String>::new(
+                MinLenFilter::new(text.split_ascii_whitespace().map(Cow::from), 3),
+                2,
+            )
+            .map(|t| (t.inner, t.idx))
+            .collect::<Vec<_>>(),
+            [
+                ("saw", 0),
+                ("saw big", 1),
+                ("big", 0),
+                ("big dog", 1),
+                ("dog", 0),
+                ("dog the", 1),
+                ("the", 0),
+                ("the park", 1),
+                ("park", 0),
+            ]
+            .into_iter()
+            .map(|(gram, idx)| (gram.to_string(), idx))
+            .collect::<Vec<_>>()
+        );
+
+        // Lengths are counted in characters rather than bytes
+        assert_eq!(
+            MinLenFilter::new(["日", "né", "é", "日本", "x"].into_iter().map(Cow::from), 2)
+                .collect::<Vec<_>>(),
+            vec!["né", "日本"]
+        );
+        assert_eq!(
+            MinLenFilter::new(["", "a"].into_iter().map(Cow::from), 0).count(),
+            2
+        );
+    }
+}
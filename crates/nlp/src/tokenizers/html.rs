@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+/// Emitted before the text of elements styled to be invisible.
+pub const HIDDEN_TEXT_TOKEN: &str = "__hidden_text__";
+
+/// Extracts the visible words of an HTML document for the
+/// [`OsbTokenizer`](super::osb::OsbTokenizer). Tags and comments are
+/// stripped, the contents of `<script>` and `<style>` are dropped and
+/// character references are decoded, with words free of references borrowed
+/// from the input. Unclosed tags extend to the end of the input, and a `<`
+/// that doesn't start a tag is kept as text.
+///
+/// When features are enabled, elements hidden with `font-size: 0`,
+/// `display: none` or `visibility: hidden` emit a `HIDDEN_TEXT_TOKEN` ahead
+/// of their text, which is still emitted.
+pub struct HtmlTokenizer<'x> {
+    html: &'x str,
+    pos: usize,
+    emit_features: bool,
+    pending: Vec<Cow<'x, str>>,
+}
+
+impl<'x> HtmlTokenizer<'x> {
+    pub fn new(html: &'x str) -> Self {
+        HtmlTokenizer {
+            html,
+            pos: 0,
+            emit_features: false,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn emit_features(mut self, emit_features: bool) -> Self {
+        self.emit_features = emit_features;
+        self
+    }
+
+    /// Skips the tag or comment at the current position, returning whether
+    /// it opens a hidden element.
+    fn skip_tag(&mut self) -> bool {
+        let html = self.html;
+        let rest = &html[self.pos..];
+
+        if rest.starts_with("<!--") {
+            self.pos = rest
+                .find("-->")
+                .map_or(html.len(), |end| self.pos + end + 3);
+            return false;
+        }
+
+        let mut quote = None;
+        let end = rest.bytes().enumerate().skip(1).find_map(|(pos, ch)| {
+            match (ch, quote) {
+                (b'>', None) => return Some(pos),
+                (b'"' | b'\'', None) => quote = Some(ch),
+                (_, Some(q)) if q == ch => quote = None,
+                _ => (),
+            }
+            None
+        });
+        let tag = &rest[1..end.unwrap_or(rest.len())];
+        self.pos = end.map_or(html.len(), |end| self.pos + end + 1);
+
+        if tag.starts_with(['/', '!', '?']) {
+            return false;
+        }
+        let name_len = tag
+            .find(|ch: char| !ch.is_ascii_alphanumeric())
+            .unwrap_or(tag.len());
+        let name = &tag[..name_len];
+
+        if name.eq_ignore_ascii_case("script") || name.eq_ignore_ascii_case("style") {
+            // Drop everything up to the closing tag
+            let rest = &html[self.pos..];
+            self.pos = rest
+                .as_bytes()
+                .windows(name.len() + 2)
+                .position(|window| {
+                    window.starts_with(b"</") && window[2..].eq_ignore_ascii_case(name.as_bytes())
+                })
+                .and_then(|start| rest[start..].find('>').map(|end| start + end + 1))
+                .map_or(html.len(), |end| self.pos + end);
+            false
+        } else {
+            is_hidden(tag)
+        }
+    }
+}
+
+impl<'x> Iterator for HtmlTokenizer<'x> {
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.pop() {
+            return Some(token);
+        }
+
+        loop {
+            let html = self.html;
+            let rest = &html[self.pos..];
+            let ch = rest.chars().next()?;
+
+            if ch == '<' && is_tag_start(rest) {
+                if self.skip_tag() && self.emit_features {
+                    return Some(HIDDEN_TEXT_TOKEN.into());
+                }
+            } else if ch.is_whitespace() {
+                self.pos += ch.len_utf8();
+            } else {
+                let len = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|(pos, ch)| {
+                        ch.is_whitespace() || (*ch == '<' && is_tag_start(&rest[*pos..]))
+                    })
+                    .map_or(rest.len(), |(pos, _)| pos);
+                let word = &rest[..len];
+                self.pos += len;
+
+                if !word.contains('&') {
+                    return Some(word.into());
+                }
+
+                // Decoded references may contain whitespace such as &nbsp;
+                self.pending = decode_entities(word)
+                    .split_whitespace()
+                    .rev()
+                    .map(|word| Cow::Owned(word.to_string()))
+                    .collect();
+                if let Some(token) = self.pending.pop() {
+                    return Some(token);
+                }
+            }
+        }
+    }
+}
+
+fn is_tag_start(text: &str) -> bool {
+    match text.as_bytes().get(1).copied() {
+        Some(b'/') => text
+            .as_bytes()
+            .get(2)
+            .map_or(false, u8::is_ascii_alphabetic),
+        Some(ch) => ch.is_ascii_alphabetic() || matches!(ch, b'!' | b'?'),
+        None => false,
+    }
+}
+
+fn is_hidden(tag: &str) -> bool {
+    let tag = tag
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    tag.contains("display:none")
+        || tag.contains("visibility:hidden")
+        || tag.split("font-size:").skip(1).any(|value| {
+            let len = value
+                .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+                .unwrap_or(value.len());
+            value[..len]
+                .parse::<f64>()
+                .map_or(false, |size| size == 0.0)
+        })
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let ch = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                entity => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map_or_else(
+                        || entity.strip_prefix('#')?.parse::<u32>().ok(),
+                        |hex| u32::from_str_radix(hex, 16).ok(),
+                    )
+                    .and_then(char::from_u32)?,
+            };
+            Some((ch, end + 1))
+        });
+
+        if let Some((ch, len)) = decoded {
+            result.push(ch);
+            rest = &rest[len..];
+        } else {
+            result.push('&');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{HtmlTokenizer, HIDDEN_TEXT_TOKEN};
+
+    #[test]
+    fn html_tokenizer() {
+        let html = concat!(
+            "<html><head><title>Cheap meds</title><style>p { color: red }</style>",
+            "<script type=\"text/javascript\">var x = \"<b>\";</script></head>",
+            "<body><!-- <p>comment</p> --><p class='a > b'>Buy <b>now</b> &amp; save!</p>",
+            "<div style=\"font-size: 0px\">lorem ipsum</div>",
+            "<p style=\"font-size:0.9em\">V&#105;agra &#x76;iagra free&nbsp;money a < b &bogus;</p>",
+            "<p>unclosed <a href=\"http://example.org\""
+        );
+
+        assert_eq!(
+            HtmlTokenizer::new(html).collect::<Vec<_>>(),
+            [
+                "Cheap", "meds", "Buy", "now", "&", "save!", "lorem", "ipsum", "Viagra", "viagra",
+                "free", "money", "a", "<", "b", "&bogus;", "unclosed"
+            ]
+        );
+        assert_eq!(
+            HtmlTokenizer::new(html)
+                .emit_features(true)
+                .filter(|token| token == HIDDEN_TEXT_TOKEN)
+                .count(),
+            1
+        );
+        assert_eq!(
+            HtmlTokenizer::new(html)
+                .emit_features(true)
+                .skip_while(|token| token != HIDDEN_TEXT_TOKEN)
+                .take(3)
+                .collect::<Vec<_>>(),
+            [HIDDEN_TEXT_TOKEN, "lorem", "ipsum"]
+        );
+
+        // Words without references are borrowed
+        assert!(HtmlTokenizer::new("<p>Buy <b>now</b></p>").all(|t| matches!(t, Cow::Borrowed(_))));
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+/// Flattens a JSON value into namespaced `json:<path>:<value>` tokens, where
+/// `<path>` joins the object keys leading to a scalar with dots. Array
+/// elements share the path of the array so that the features do not depend
+/// on item positions.
+pub struct JsonTokenizer<'x> {
+    stack: Vec<(String, &'x Value)>,
+}
+
+impl<'x> JsonTokenizer<'x> {
+    pub fn new(value: &'x Value) -> Self {
+        JsonTokenizer {
+            stack: vec![(String::new(), value)],
+        }
+    }
+}
+
+impl<'x> Iterator for JsonTokenizer<'x> {
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, value) = self.stack.pop()?;
+            let value = match value {
+                Value::Object(map) => {
+                    for (key, value) in map.iter().rev() {
+                        let key = key.trim().to_lowercase();
+                        self.stack.push((
+                            if path.is_empty() {
+                                key
+                            } else {
+                                format!("{path}.{key}")
+                            },
+                            value,
+                        ));
+                    }
+                    continue;
+                }
+                Value::Array(items) => {
+                    for value in items.iter().rev() {
+                        self.stack.push((path.clone(), value));
+                    }
+                    continue;
+                }
+                Value::String(value) => value.trim().to_lowercase(),
+                Value::Number(value) => value.to_string(),
+                Value::Bool(value) => value.to_string(),
+                Value::Null => "null".to_string(),
+            };
+
+            return Some(format!("json:{path}:{value}").into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonTokenizer;
+
+    #[test]
+    fn json_tokenizer() {
+        let value = serde_json::json!({
+            "order": {
+                "total": 49.99,
+                "currency": "USD",
+                "items": [
+                    {"name": "Gift Card", "qty": 2},
+                    {"name": "Shipping", "qty": 1}
+                ],
+                "paid": false,
+                "coupon": null
+            },
+            "Sender": " Shop "
+        });
+
+        let mut tokens = JsonTokenizer::new(&value).collect::<Vec<_>>();
+        tokens.sort_unstable();
+
+        assert_eq!(
+            tokens,
+            vec![
+                "json:order.coupon:null",
+                "json:order.currency:usd",
+                "json:order.items.name:gift card",
+                "json:order.items.name:shipping",
+                "json:order.items.qty:1",
+                "json:order.items.qty:2",
+                "json:order.paid:false",
+                "json:order.total:49.99",
+                "json:sender:shop",
+            ]
+        );
+    }
+}
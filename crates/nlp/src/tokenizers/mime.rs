@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use mail_parser::decoders::{
+    base64::base64_decode, charsets::map::charset_decoder,
+    quoted_printable::quoted_printable_decode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferEncoding {
+    /// `7bit`, `8bit`, `binary` or an unrecognized encoding.
+    Identity,
+    QuotedPrintable,
+    Base64,
+}
+
+impl TransferEncoding {
+    /// Parses the value of a `Content-Transfer-Encoding` header.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("quoted-printable") {
+            TransferEncoding::QuotedPrintable
+        } else if value.eq_ignore_ascii_case("base64") {
+            TransferEncoding::Base64
+        } else {
+            TransferEncoding::Identity
+        }
+    }
+}
+
+/// Decodes a MIME text body into UTF-8 ready to be split into tokens.
+///
+/// The transfer encoding is removed first: soft line breaks are joined in
+/// quoted-printable bodies and missing base64 padding is tolerated. Bodies
+/// that fail to decode are used as they are rather than dropped, as spam
+/// often carries deliberately broken encodings. The result is then
+/// transcoded from `charset`, with UTF-8 or unknown charsets decoded
+/// lossily. Unencoded UTF-8 bodies are borrowed.
+pub fn decode_text<'x>(
+    bytes: &'x [u8],
+    encoding: TransferEncoding,
+    charset: Option<&str>,
+) -> Cow<'x, str> {
+    let bytes = match encoding {
+        TransferEncoding::Identity => None,
+        TransferEncoding::QuotedPrintable => quoted_printable_decode(bytes),
+        TransferEncoding::Base64 => decode_base64(bytes),
+    }
+    .map_or(Cow::Borrowed(bytes), Cow::Owned);
+
+    match charset
+        .filter(|charset| {
+            !charset.eq_ignore_ascii_case("utf-8") && !charset.eq_ignore_ascii_case("us-ascii")
+        })
+        .and_then(|charset| charset_decoder(charset.as_bytes()))
+    {
+        Some(decoder) => decoder(&bytes).into(),
+        None => match bytes {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => text.into(),
+                Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned().into(),
+            },
+        },
+    }
+}
+
+fn decode_base64(bytes: &[u8]) -> Option<Vec<u8>> {
+    // Restore the padding of bodies truncated or encoded without it
+    let end = bytes
+        .iter()
+        .rposition(|ch| !ch.is_ascii_whitespace() && *ch != b'=')
+        .map_or(0, |pos| pos + 1);
+    let bytes = &bytes[..end];
+    let padding = match bytes.iter().filter(|ch| !ch.is_ascii_whitespace()).count() % 4 {
+        2 => "==",
+        3 => "=",
+        _ => return base64_decode(bytes),
+    };
+
+    base64_decode(&[bytes, padding.as_bytes()].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{decode_text, TransferEncoding};
+
+    #[test]
+    fn decode_quoted_printable() {
+        let encoding = TransferEncoding::parse(" Quoted-Printable");
+        assert_eq!(encoding, TransferEncoding::QuotedPrintable);
+
+        assert_eq!(
+            decode_text(
+                b"Don=E2=80=99t miss this great=\r\n offer!",
+                encoding,
+                Some("utf-8")
+            ),
+            "Don\u{2019}t miss this great offer!"
+        );
+        assert_eq!(
+            decode_text(b"Caf=E9 cr=E8me", encoding, Some("ISO-8859-1")),
+            "Café crème"
+        );
+
+        // Broken encodings are kept as they are
+        assert_eq!(decode_text(b"50% off =ZZ", encoding, None), "50% off =ZZ");
+    }
+
+    #[test]
+    fn decode_base64() {
+        let encoding = TransferEncoding::parse("base64");
+        assert_eq!(encoding, TransferEncoding::Base64);
+
+        for body in [
+            "SGVsbG8gd29ybGQ=".as_bytes(),
+            b"SGVsbG8g\r\nd29ybGQ=\r\n",
+            b"SGVsbG8gd29ybGQ",
+            b"SGVsbG8gd29ybGQ===",
+        ] {
+            assert_eq!(decode_text(body, encoding, None), "Hello world");
+        }
+        assert_eq!(
+            decode_text(b"Q2Fm6Q==", encoding, Some("iso-8859-1")),
+            "Café"
+        );
+    }
+
+    #[test]
+    fn decode_identity() {
+        let encoding = TransferEncoding::parse("8bit");
+        assert_eq!(encoding, TransferEncoding::Identity);
+
+        assert!(matches!(
+            decode_text("Café".as_bytes(), encoding, Some("UTF-8")),
+            Cow::Borrowed("Café")
+        ));
+        assert_eq!(decode_text(b"Caf\xe9", encoding, Some("latin1")), "Café");
+        assert_eq!(decode_text(b"Caf\xe9", encoding, None), "Caf\u{fffd}");
+    }
+}
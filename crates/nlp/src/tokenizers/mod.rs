@@ -21,11 +21,27 @@
  * for more details.
 */
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod chinese;
+pub mod cjk;
+pub mod dedup;
+pub mod filter;
+pub mod html;
 pub mod japanese;
+pub mod json;
+pub mod mime;
+pub mod normalize;
 pub mod osb;
+pub mod position;
+pub mod quote;
+pub mod rejoin;
+pub mod script;
+pub mod shingle;
 pub mod space;
+pub mod split;
 pub mod types;
+pub mod url;
 pub mod word;
 
 use std::borrow::Cow;
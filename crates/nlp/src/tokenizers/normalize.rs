@@ -0,0 +1,252 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Lowercases tokens and folds compatibility characters and diacritics
+/// (`Ｃａｆé` -> `cafe`) so that accented or full-width lookalikes map to the
+/// same grams as their plain spelling. Tokens that are left empty, such as
+/// those made only of combining marks, are dropped. Lowercase ASCII tokens
+/// are passed through unchanged, keeping them borrowed.
+pub struct Normalizer<I> {
+    iter: I,
+}
+
+impl<I> Normalizer<I> {
+    pub fn new(iter: I) -> Self {
+        Normalizer { iter }
+    }
+}
+
+impl<'x, I> Iterator for Normalizer<I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.by_ref().find_map(normalize)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Normalizes a single token, returning `None` when nothing is left.
+pub fn normalize(token: Cow<'_, str>) -> Option<Cow<'_, str>> {
+    if token.is_ascii() {
+        if token.is_empty() {
+            None
+        } else if token.bytes().any(|ch| ch.is_ascii_uppercase()) {
+            Some(token.to_ascii_lowercase().into())
+        } else {
+            Some(token)
+        }
+    } else {
+        // Compatibility decomposition separates the combining marks from
+        // their base letters, which are recomposed once the marks are gone.
+        // Dotted capital I decomposes to I + U+0307, and dotless i is folded
+        // explicitly, so both Turkish variants end up as a plain `i`.
+        let normalized = token
+            .nfkd()
+            .filter(|ch| !is_combining_mark(*ch))
+            .map(|ch| if ch == 'ı' { 'i' } else { ch })
+            .flat_map(char::to_lowercase)
+            .nfc()
+            .collect::<String>();
+
+        if !normalized.is_empty() {
+            Some(normalized.into())
+        } else {
+            None
+        }
+    }
+}
+
+/// Shortest run of a repeated character that [`RepeatCollapser`] collapses,
+/// so that legitimate double letters such as in `balloon` are kept.
+pub const MIN_REPEAT_RUN: usize = 3;
+
+/// Collapses runs of [`MIN_REPEAT_RUN`] or more identical characters, as in
+/// elongated words like `freeeee`, down to two characters by default, so
+/// that they map to the same grams as their plain spelling. Characters are
+/// compared as Unicode scalar values. Tokens without such runs are passed
+/// through unchanged.
+pub struct RepeatCollapser<I> {
+    iter: I,
+    keep: usize,
+}
+
+impl<I> RepeatCollapser<I> {
+    pub fn new(iter: I) -> Self {
+        RepeatCollapser { iter, keep: 2 }
+    }
+
+    /// Sets the number of characters a collapsed run is reduced to, which
+    /// is clamped to `1..MIN_REPEAT_RUN`.
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.keep = keep.clamp(1, MIN_REPEAT_RUN - 1);
+        self
+    }
+}
+
+impl<'x, I> Iterator for RepeatCollapser<I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|token| collapse_repeats(token, self.keep))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Collapses the runs of [`MIN_REPEAT_RUN`] or more identical characters in
+/// a single token down to `keep` characters.
+pub fn collapse_repeats(token: Cow<'_, str>, keep: usize) -> Cow<'_, str> {
+    let mut run = 0;
+    let mut last = None;
+    let has_run = token.chars().any(|ch| {
+        if last == Some(ch) {
+            run += 1;
+        } else {
+            run = 1;
+            last = Some(ch);
+        }
+        run >= MIN_REPEAT_RUN
+    });
+    if !has_run {
+        return token;
+    }
+
+    let mut collapsed = String::with_capacity(token.len());
+    let mut chars = token.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let mut run = 1;
+        while chars.next_if_eq(&ch).is_some() {
+            run += 1;
+        }
+        let len = if run >= MIN_REPEAT_RUN { keep } else { run };
+        collapsed.extend(std::iter::repeat(ch).take(len));
+    }
+    collapsed.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{collapse_repeats, normalize, Normalizer, RepeatCollapser};
+
+    #[test]
+    fn normalize_tokens() {
+        for (input, expected) in [
+            ("Café", Some("cafe")),
+            ("CAFÉ", Some("cafe")),
+            ("naïve", Some("naive")),
+            ("Ｖｉａｇｒａ", Some("viagra")),
+            ("ﬁnance", Some("finance")),
+            ("İstanbul", Some("istanbul")),
+            ("ıstanbul", Some("istanbul")),
+            ("ISTANBUL", Some("istanbul")),
+            ("한국어", Some("한국어")),
+            ("\u{0301}\u{0308}", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                normalize(Cow::from(input)).as_deref(),
+                expected,
+                "failed for {input:?}"
+            );
+        }
+
+        // Lowercase ASCII stays borrowed
+        assert!(matches!(
+            normalize(Cow::from("cafe")),
+            Some(Cow::Borrowed("cafe"))
+        ));
+
+        assert_eq!(
+            Normalizer::new(
+                ["Café", "\u{0301}", "au", "LAIT"]
+                    .into_iter()
+                    .map(Cow::from)
+            )
+            .collect::<Vec<_>>(),
+            vec!["cafe", "au", "lait"]
+        );
+    }
+
+    #[test]
+    fn collapse_repeated_chars() {
+        for (input, keep, expected) in [
+            ("freeeee", 2, "free"),
+            ("free", 2, "free"),
+            ("balloon", 2, "balloon"),
+            ("balloon", 1, "balloon"),
+            ("freeeee", 1, "fre"),
+            ("!!!!!wiiiin!!!", 2, "!!wiin!!"),
+            ("🔥🔥🔥🔥🔥", 2, "🔥🔥"),
+            ("🔥🔥", 1, "🔥🔥"),
+            ("ééééé", 1, "é"),
+            ("", 2, ""),
+        ] {
+            assert_eq!(
+                collapse_repeats(Cow::from(input), keep),
+                expected,
+                "failed for {input:?}"
+            );
+        }
+
+        // Tokens without runs stay borrowed
+        assert!(matches!(
+            collapse_repeats(Cow::from("balloon"), 2),
+            Cow::Borrowed("balloon")
+        ));
+
+        assert_eq!(
+            RepeatCollapser::new(
+                ["freeeee", "balloon", "😂😂😂😂"]
+                    .into_iter()
+                    .map(Cow::from)
+            )
+            .collect::<Vec<_>>(),
+            vec!["free", "balloon", "😂😂"]
+        );
+        assert_eq!(
+            RepeatCollapser::new(["freeeee", "cooool"].into_iter().map(Cow::from))
+                .keep(1)
+                .collect::<Vec<_>>(),
+            vec!["fre", "col"]
+        );
+    }
+}
@@ -21,104 +21,1914 @@
  * for more details.
 */
 
-use std::{borrow::Cow, iter::Peekable};
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    iter::{Fuse, FusedIterator, Peekable},
+    rc::Rc,
+};
+
+use xxhash_rust::xxh3::{xxh3_64_with_seed, Xxh3};
+
+/// Separator used by [`Gram::to_feature_key`] for on-disk model keys.
+pub const FEATURE_KEY_SEPARATOR: u8 = 0;
+
+/// Largest window accepted by [`OsbTokenizer::try_new`], which bounds the
+/// size of the window buffer.
+pub const MAX_WINDOW_SIZE: usize = 64;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OsbToken<T> {
     pub inner: T,
     pub idx: usize,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Gram<'x> {
-    Uni { t1: &'x str },
-    Bi { t1: &'x str, t2: &'x str },
-}
+/// Grams are ordered by arity, unigrams before bigrams before trigrams, and
+/// then lexicographically by their tokens, which gives a stable order for
+/// sorting features when serializing a model.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Gram<'x> {
+    Uni {
+        t1: &'x str,
+    },
+    Bi {
+        t1: &'x str,
+        t2: &'x str,
+    },
+    Tri {
+        t1: &'x str,
+        t2: &'x str,
+        t3: &'x str,
+    },
+}
+
+impl Gram<'_> {
+    /// Hashes the gram with xxh3 without allocating. Bigrams and trigrams
+    /// hash as their tokens joined by a single space, so with a seed of 0
+    /// the result matches the `h1` half of the gram's `TokenHash`.
+    pub fn hash64(&self, seed: u64) -> u64 {
+        match self {
+            Gram::Uni { t1 } => xxh3_64_with_seed(t1.as_bytes(), seed),
+            Gram::Bi { t1, t2 } => {
+                let mut hasher = Xxh3::with_seed(seed);
+                hasher.update(t1.as_bytes());
+                hasher.update(b" ");
+                hasher.update(t2.as_bytes());
+                hasher.digest()
+            }
+            Gram::Tri { t1, t2, t3 } => {
+                let mut hasher = Xxh3::with_seed(seed);
+                hasher.update(t1.as_bytes());
+                hasher.update(b" ");
+                hasher.update(t2.as_bytes());
+                hasher.update(b" ");
+                hasher.update(t3.as_bytes());
+                hasher.digest()
+            }
+        }
+    }
+}
+
+impl Gram<'_> {
+    /// Joins the tokens of the gram with `sep` into the key used to store the
+    /// feature in a model. Tokens may themselves contain spaces, so with a
+    /// space separator `"new york" + "city"` and `"new" + "york city"` map to
+    /// the same key; use [`FEATURE_KEY_SEPARATOR`] (NUL), which tokenizers
+    /// never emit, to keep keys unambiguous.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sep` is not an ASCII byte.
+    pub fn to_feature_key(&self, sep: u8) -> String {
+        assert!(sep.is_ascii(), "feature key separator must be ASCII");
+        let sep = sep as char;
+        match self {
+            Gram::Uni { t1 } => t1.to_string(),
+            Gram::Bi { t1, t2 } => {
+                let mut key = String::with_capacity(t1.len() + t2.len() + 1);
+                key.push_str(t1);
+                key.push(sep);
+                key.push_str(t2);
+                key
+            }
+            Gram::Tri { t1, t2, t3 } => {
+                let mut key = String::with_capacity(t1.len() + t2.len() + t3.len() + 2);
+                key.push_str(t1);
+                key.push(sep);
+                key.push_str(t2);
+                key.push(sep);
+                key.push_str(t3);
+                key
+            }
+        }
+    }
+}
+
+impl From<Gram<'_>> for u64 {
+    fn from(value: Gram<'_>) -> Self {
+        value.hash64(0)
+    }
+}
+
+impl<T: AsRef<str>> OsbToken<T> {
+    /// Returns a wrapper that formats the token with its text replaced by its
+    /// length and hash, for logging tokens without exposing message content.
+    /// The hash is unkeyed, so it only hides text that can't be guessed.
+    pub fn redacted(&self) -> RedactedToken<'_, T> {
+        RedactedToken(self)
+    }
+}
+
+/// Formats an [`OsbToken`] without its text, see [`OsbToken::redacted`].
+pub struct RedactedToken<'x, T>(&'x OsbToken<T>);
+
+impl<T: AsRef<str>> RedactedToken<'_, T> {
+    fn digest(&self) -> (usize, u64) {
+        let text = self.0.inner.as_ref();
+        (text.len(), xxh3_64_with_seed(text.as_bytes(), 0))
+    }
+}
+
+impl<T: AsRef<str>> Debug for RedactedToken<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (len, hash) = self.digest();
+        f.debug_struct("OsbToken")
+            .field(
+                "inner",
+                &format_args!("<redacted len={len} hash={hash:016x}>"),
+            )
+            .field("idx", &self.0.idx)
+            .finish()
+    }
+}
+
+impl<T: AsRef<str>> Display for RedactedToken<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (len, hash) = self.digest();
+        write!(
+            f,
+            "<redacted len={len} hash={hash:016x}> idx={}",
+            self.0.idx
+        )
+    }
+}
+
+/// Splits unigrams and bigrams into their tokens without copying them, for
+/// looking grams up in maps keyed by token pairs. Grams borrow the window
+/// buffer of the tokenizer, so the pair can't be used as `R` and is obtained
+/// through [`OsbTokenizer::next_gram`] instead. Trigrams don't fit a pair and
+/// are returned as the error.
+impl<'x> TryFrom<Gram<'x>> for (&'x str, Option<&'x str>) {
+    type Error = Gram<'x>;
+
+    fn try_from(value: Gram<'x>) -> Result<Self, Self::Error> {
+        match value {
+            Gram::Uni { t1 } => Ok((t1, None)),
+            Gram::Bi { t1, t2 } => Ok((t1, Some(t2))),
+            Gram::Tri { .. } => Err(value),
+        }
+    }
+}
+
+/// Token that [`TailMode::Pad`] pairs the last head tokens of a stream with
+/// in place of the missing tokens of their windows.
+pub const TAIL_PAD_TOKEN: &str = "__end__";
+
+/// How an [`OsbTokenizer`] emits the head tokens at the end of the stream,
+/// which no longer have `window_size - 1` tokens following them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TailMode {
+    /// Emit the shorter windows, only pairing the head token with the tokens
+    /// left in the stream.
+    #[default]
+    Shrink,
+    /// Emit full windows, pairing the head token with [`TAIL_PAD_TOKEN`] for
+    /// each missing token, so every head token has `window_size` grams.
+    Pad,
+    /// Suppress the head tokens whose window is incomplete.
+    Drop,
+}
+
+/// Counters collected by an [`OsbTokenizer`] when enabled with
+/// [`OsbTokenizer::collect_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenizerStats {
+    pub grams: usize,
+    pub unigrams: usize,
+    pub bigrams: usize,
+    pub trigrams: usize,
+    /// Head tokens that emitted fewer than `window_size` grams because the
+    /// stream ended before their window was filled.
+    pub truncated_windows: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsbError {
+    ZeroWindowSize,
+    WindowSizeTooLarge(usize),
+}
+
+pub struct OsbTokenizer<'x, I, R>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    // Fused as the end of the stream is detected more than once per window
+    iter: Peekable<Fuse<I>>,
+    buf: Vec<Option<Cow<'x, str>>>,
+    folded: Vec<Option<String>>,
+    window_size: usize,
+    window_pos: usize,
+    window_idx: usize,
+    tail_mode: TailMode,
+    trigrams: bool,
+    max_grams: Option<usize>,
+    remaining_grams: usize,
+    stats: Option<TokenizerStats>,
+    head_pos: Option<usize>,
+    emit_original_case: bool,
+    pending: Option<OsbToken<R>>,
+    phantom: std::marker::PhantomData<R>,
+}
+
+/// Emits the grams of an [`OsbTokenizer`] in reverse order, reading the
+/// tokens from the back of the input and buffering at most `window_size` of
+/// them. Head tokens are visited last to first and, for each of them, the
+/// bigrams are emitted from the farthest to the nearest followed by the
+/// unigram. `idx` keeps its forward meaning, the distance between the two
+/// tokens of the gram (0 for unigrams), so reversed grams hash and weigh the
+/// same as their forward counterparts.
+pub struct ReversedOsbTokenizer<'x, I, R>
+where
+    I: DoubleEndedIterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    iter: I,
+    window: VecDeque<Cow<'x, str>>,
+    window_size: usize,
+    window_idx: Option<usize>,
+    phantom: std::marker::PhantomData<R>,
+}
+
+impl<'x, I, R> OsbTokenizer<'x, I, R>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero or larger than [`MAX_WINDOW_SIZE`],
+    /// use [`OsbTokenizer::try_new`] for sizes read from configuration.
+    pub fn new(iter: I, window_size: usize) -> Self {
+        match Self::try_new(iter, window_size) {
+            Ok(tokenizer) => tokenizer,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    pub fn try_new(iter: I, window_size: usize) -> Result<Self, OsbError> {
+        validate_window_size(window_size)?;
+
+        Ok(Self {
+            iter: iter.fuse().peekable(),
+            buf: vec![None; window_size],
+            folded: Vec::new(),
+            window_pos: 0,
+            window_idx: 0,
+            window_size,
+            tail_mode: TailMode::Shrink,
+            trigrams: false,
+            max_grams: None,
+            remaining_grams: usize::MAX,
+            stats: None,
+            head_pos: None,
+            emit_original_case: false,
+            pending: None,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Creates a tokenizer emitting at most `max_grams` grams. Unlike
+    /// `take(max_grams)`, the stream stops on a head token boundary: a head
+    /// token is only emitted when all of its grams fit within the limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero or larger than [`MAX_WINDOW_SIZE`].
+    pub fn with_limit(iter: I, window_size: usize, max_grams: usize) -> Self {
+        let mut tokenizer = Self::new(iter, window_size);
+        tokenizer.max_grams = Some(max_grams);
+        tokenizer.remaining_grams = max_grams;
+        tokenizer
+    }
+
+    /// Restarts the tokenizer over `iter` with a new window size, keeping the
+    /// tail mode, trigram, case folding and limit settings. Collected stats
+    /// are cleared. The window buffer is reused and only reallocated when
+    /// `window_size` exceeds its capacity, so the same tokens can be compared
+    /// at several window sizes without rebuilding the tokenizer. As the
+    /// previous iterator may have been partially consumed, `iter` must
+    /// restart the stream from the beginning, for example by iterating a
+    /// `Vec` of tokens again.
+    pub fn reset_with_window(&mut self, iter: I, window_size: usize) -> Result<(), OsbError> {
+        validate_window_size(window_size)?;
+
+        self.iter = iter.fuse().peekable();
+        self.buf.clear();
+        self.buf.resize(window_size, None);
+        if !self.folded.is_empty() {
+            self.folded.clear();
+            self.folded.resize(window_size, None);
+        }
+        self.window_pos = 0;
+        self.window_idx = 0;
+        self.window_size = window_size;
+        self.remaining_grams = self.max_grams.unwrap_or(usize::MAX);
+        self.head_pos = None;
+        self.pending = None;
+        if let Some(stats) = &mut self.stats {
+            *stats = TokenizerStats::default();
+        }
+        Ok(())
+    }
+
+    /// Controls whether the head tokens at the end of the stream, which no
+    /// longer have `window_size - 1` tokens following them, emit their
+    /// shorter windows (the default) or are suppressed. Shorthand for
+    /// [`OsbTokenizer::tail_mode`] with [`TailMode::Shrink`] or
+    /// [`TailMode::Drop`].
+    pub fn flush_partial(mut self, flush_partial: bool) -> Self {
+        self.tail_mode = if flush_partial {
+            TailMode::Shrink
+        } else {
+            TailMode::Drop
+        };
+        self
+    }
+
+    /// Controls how the head tokens at the end of the stream, which no
+    /// longer have `window_size - 1` tokens following them, are emitted.
+    pub fn tail_mode(mut self, tail_mode: TailMode) -> Self {
+        self.tail_mode = tail_mode;
+        self
+    }
+
+    /// Controls whether each head token also emits the contiguous trigram
+    /// formed with the two tokens following it, after its unigram and
+    /// skip-bigrams. Trigrams use `idx = window_size` so that the indexes of
+    /// unigrams and bigrams are unchanged. Windows smaller than 3 never emit
+    /// trigrams.
+    pub fn emit_trigrams(mut self, trigrams: bool) -> Self {
+        self.trigrams = trigrams;
+        self
+    }
+
+    /// Controls whether the tokenizer counts the grams it emits, which can
+    /// be read with [`OsbTokenizer::stats`]. Counting is disabled by default.
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.stats = collect_stats.then(TokenizerStats::default);
+        self
+    }
+
+    /// Returns the counters collected so far, if enabled.
+    pub fn stats(&self) -> Option<&TokenizerStats> {
+        self.stats.as_ref()
+    }
+
+    /// Returns the zero-based position in the input of the head token `t1`
+    /// of the last gram emitted, or `None` before the first gram.
+    pub fn head_pos(&self) -> Option<usize> {
+        self.head_pos
+    }
+
+    /// Controls whether grams are built from lowercased tokens, making `R`
+    /// case insensitive while the original tokens remain available through
+    /// [`OsbTokenizer::next_cased_gram`]. Tokens without uppercase letters
+    /// are not copied.
+    pub fn fold_case(mut self, fold_case: bool) -> Self {
+        self.folded = if fold_case {
+            vec![None; self.window_size]
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// Controls whether grams whose tokens differ from their case folded
+    /// form are also emitted as built from the original tokens, right after
+    /// the folded gram and with the same `idx`. These extra grams never take
+    /// a window position, so the skip distances are unchanged, and they are
+    /// not counted by the gram limit or the stats. Enables
+    /// [`OsbTokenizer::fold_case`].
+    pub fn emit_original_case(mut self, emit_original_case: bool) -> Self {
+        if emit_original_case && self.folded.is_empty() {
+            self = self.fold_case(true);
+        }
+        self.emit_original_case = emit_original_case;
+        self
+    }
+}
+
+impl<'x, I, R> OsbTokenizer<'x, I, R>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    /// Advances the tokenizer, mapping the next gram with `f` instead of
+    /// converting it into `R`.
+    pub fn next_gram<T>(&mut self, f: impl FnOnce(Gram<'_>) -> T) -> Option<OsbToken<T>> {
+        self.next_cased_gram(|gram, _| f(gram))
+    }
+
+    /// Advances the tokenizer, mapping the next gram with `f`, which receives
+    /// the gram of case folded tokens that `R` is built from followed by the
+    /// gram of the original tokens, for example to highlight the words that
+    /// matched a case insensitive feature. Both grams are the same unless
+    /// [`OsbTokenizer::fold_case`] is enabled.
+    pub fn next_cased_gram<T>(
+        &mut self,
+        f: impl FnOnce(Gram<'_>, Gram<'_>) -> T,
+    ) -> Option<OsbToken<T>> {
+        if self.window_idx == self.window_size {
+            let positions = [0, 1, 2].map(|offset| (self.window_pos + offset) % self.window_size);
+            let token = OsbToken {
+                inner: f(self.gram(&positions, true)?, self.gram(&positions, false)?),
+                idx: self.window_size,
+            };
+            if let Some(stats) = &mut self.stats {
+                stats.grams += 1;
+                stats.trigrams += 1;
+            }
+            self.head_pos = Some(self.window_pos);
+            self.advance();
+            return Some(token);
+        }
+
+        if self.window_idx == 0 && !self.reserve_head() {
+            return None;
+        }
+
+        if self.window_idx == 0 && self.tail_mode == TailMode::Drop {
+            // Make sure the window can be filled before emitting the head token
+            for offset in 0..self.window_size {
+                if !self.fill((self.window_pos + offset) % self.window_size) {
+                    return None;
+                }
+            }
+        }
+
+        let end_pos = (self.window_pos + self.window_idx) % self.window_size;
+        self.fill(end_pos);
+
+        let head_pos = self.window_pos % self.window_size;
+        let positions = if self.window_idx != 0 {
+            &[head_pos, end_pos][..]
+        } else {
+            &[head_pos][..]
+        };
+        let token = OsbToken {
+            inner: f(self.gram(positions, true)?, self.gram(positions, false)?),
+            idx: self.window_idx,
+        };
+        self.head_pos = Some(self.window_pos);
+        if let Some(stats) = &mut self.stats {
+            stats.grams += 1;
+            if self.window_idx == 0 {
+                stats.unigrams += 1;
+            } else {
+                stats.bigrams += 1;
+            }
+        }
+
+        // Increment window index
+        self.window_idx += 1;
+        if self.window_idx == self.window_size
+            || (self.tail_mode != TailMode::Pad
+                && self.iter.peek().is_none()
+                && self.buf[(self.window_pos + self.window_idx) % self.window_size].is_none())
+        {
+            if let Some(stats) = &mut self.stats {
+                if self.window_idx < self.window_size {
+                    stats.truncated_windows += 1;
+                }
+            }
+            if self.has_trigram() {
+                self.window_idx = self.window_size;
+            } else {
+                self.advance();
+            }
+        }
+
+        Some(token)
+    }
+
+    /// Number of grams left to emit given the number of tokens from the
+    /// current head token onwards.
+    fn remaining_grams(&self, tokens: usize) -> usize {
+        let window_size = self.window_size;
+        let heads = if self.tail_mode != TailMode::Drop {
+            tokens
+        } else {
+            (tokens + 1).saturating_sub(window_size)
+        };
+        let mut grams = match self.tail_mode {
+            TailMode::Shrink if tokens < window_size => tokens * (tokens + 1) / 2,
+            TailMode::Shrink => heads
+                .saturating_mul(window_size)
+                .saturating_sub(window_size * (window_size - 1) / 2),
+            TailMode::Pad | TailMode::Drop => heads.saturating_mul(window_size),
+        };
+        if self.trigrams && window_size >= 3 {
+            grams = grams.saturating_add(if self.tail_mode != TailMode::Drop {
+                tokens.saturating_sub(2)
+            } else {
+                heads
+            });
+        }
+
+        // A pending trigram follows all the grams of a possibly partial window
+        grams.saturating_sub(if self.window_idx == window_size {
+            if self.tail_mode == TailMode::Pad {
+                window_size
+            } else {
+                window_size.min(tokens)
+            }
+        } else {
+            self.window_idx
+        })
+    }
+
+    /// Bounds on the number of grams left that take a window position.
+    fn window_size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.buf.iter().filter(|token| token.is_some()).count();
+        let (lower, upper) = self.iter.size_hint();
+
+        if self.max_grams.is_some() {
+            // The grams of the current head token were already deducted
+            let limit = self.remaining_grams.saturating_add(self.window_size + 1);
+            return (
+                0,
+                Some(
+                    upper
+                        .and_then(|upper| buffered.checked_add(upper))
+                        .map_or(limit, |tokens| self.remaining_grams(tokens).min(limit)),
+                ),
+            );
+        }
+
+        (
+            self.remaining_grams(buffered.saturating_add(lower)),
+            upper
+                .and_then(|upper| buffered.checked_add(upper))
+                .map(|tokens| self.remaining_grams(tokens)),
+        )
+    }
+
+    /// Returns the buffered tokens in window order, starting with the current
+    /// head token. Slots are `None` until their token is read from the input
+    /// and after the end of the stream. Tokens are returned with their
+    /// original case.
+    pub fn current_window<'s>(&'s self) -> impl Iterator<Item = Option<&'s str>> + 's {
+        // Shortening the lifetime of the tokens keeps 'x out of the return type
+        let buf: &'s [Option<Cow<'s, str>>] = &self.buf;
+        let (window_pos, window_size) = (self.window_pos, self.window_size);
+
+        (0..window_size).map(move |offset| buf[(window_pos + offset) % window_size].as_deref())
+    }
+
+    /// Reads tokens from the source until the current window is full, so
+    /// that the cost of parsing them isn't paid by the next call to `next`.
+    /// Calling it again, or on an exhausted source, has no effect and the
+    /// emitted grams are the same as without prefilling.
+    pub fn prefill(&mut self) {
+        for offset in 0..self.window_size {
+            if !self.fill((self.window_pos + offset) % self.window_size) {
+                break;
+            }
+        }
+    }
+
+    /// Deducts the grams of the next head token from the limit, if any,
+    /// returning `false` when they don't fit.
+    fn reserve_head(&mut self) -> bool {
+        if self.max_grams.is_none() {
+            return true;
+        }
+
+        let mut tokens = 0;
+        for offset in 0..self.window_size {
+            if !self.fill((self.window_pos + offset) % self.window_size) {
+                break;
+            }
+            tokens += 1;
+        }
+        let grams = if self.tail_mode == TailMode::Pad && tokens > 0 {
+            self.window_size
+        } else {
+            tokens
+        } + usize::from(self.trigrams && tokens >= 3);
+
+        if grams <= self.remaining_grams {
+            self.remaining_grams -= grams;
+            true
+        } else {
+            self.remaining_grams = 0;
+            false
+        }
+    }
+
+    fn has_trigram(&self) -> bool {
+        self.trigrams
+            && self.window_size >= 3
+            && (1..3)
+                .all(|offset| self.buf[(self.window_pos + offset) % self.window_size].is_some())
+    }
+
+    /// Reads the next token into the slot `pos` if it is empty, returning
+    /// whether the slot holds a token.
+    fn fill(&mut self, pos: usize) -> bool {
+        if self.buf[pos].is_none() {
+            self.buf[pos] = self.iter.next();
+            if let (Some(folded), Some(token)) = (self.folded.get_mut(pos), &self.buf[pos]) {
+                *folded = token
+                    .chars()
+                    .any(char::is_uppercase)
+                    .then(|| token.to_lowercase());
+            }
+        }
+        self.buf[pos].is_some()
+    }
+
+    /// Builds the gram of the tokens at `positions`, case folded if `folded`
+    /// and case folding is enabled.
+    fn gram(&self, positions: &[usize], folded: bool) -> Option<Gram<'_>> {
+        let token = |pos: usize| {
+            folded
+                .then(|| self.folded.get(pos)?.as_deref())
+                .flatten()
+                .or(self.buf[pos].as_deref())
+        };
+
+        Some(match *positions {
+            [t1] => Gram::Uni { t1: token(t1)? },
+            [t1, t2] => Gram::Bi {
+                t1: token(t1)?,
+                t2: token(t2).or(if self.tail_mode == TailMode::Pad {
+                    Some(TAIL_PAD_TOKEN)
+                } else {
+                    None
+                })?,
+            },
+            [t1, t2, t3] => Gram::Tri {
+                t1: token(t1)?,
+                t2: token(t2)?,
+                t3: token(t3)?,
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn advance(&mut self) {
+        let pos = self.window_pos % self.window_size;
+        self.buf[pos] = None;
+        if let Some(folded) = self.folded.get_mut(pos) {
+            *folded = None;
+        }
+        self.window_idx = 0;
+        self.window_pos += 1;
+    }
+
+    /// Advances the tokenizer formatting the next gram into `scratch`, with
+    /// bigram tokens separated by a space, avoiding an allocation per gram.
+    /// The returned slice is valid until `scratch` is next modified.
+    pub fn next_str<'s>(&mut self, scratch: &'s mut String) -> Option<OsbToken<&'s str>> {
+        let idx = self
+            .next_gram(|gram| {
+                scratch.clear();
+                match gram {
+                    Gram::Uni { t1 } => scratch.push_str(t1),
+                    Gram::Bi { t1, t2 } => {
+                        scratch.push_str(t1);
+                        scratch.push(' ');
+                        scratch.push_str(t2);
+                    }
+                    Gram::Tri { t1, t2, t3 } => {
+                        scratch.push_str(t1);
+                        scratch.push(' ');
+                        scratch.push_str(t2);
+                        scratch.push(' ');
+                        scratch.push_str(t3);
+                    }
+                }
+            })?
+            .idx;
+
+        Some(OsbToken {
+            inner: scratch.as_str(),
+            idx,
+        })
+    }
+}
+
+impl<'x, I, R> Iterator for OsbTokenizer<'x, I, R>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    type Item = OsbToken<R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+        if !self.emit_original_case {
+            return self.next_gram(|gram| R::from(gram));
+        }
+
+        let token = self.next_cased_gram(|folded, original| {
+            let original = (original != folded).then(|| R::from(original));
+            (R::from(folded), original)
+        })?;
+        let (folded, original) = token.inner;
+        self.pending = original.map(|inner| OsbToken {
+            inner,
+            idx: token.idx,
+        });
+        Some(OsbToken {
+            inner: folded,
+            idx: token.idx,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.window_size_hint();
+        let pending = usize::from(self.pending.is_some());
+        (
+            lower.saturating_add(pending),
+            upper.and_then(|upper| {
+                // Every gram may be followed by its original case form
+                let factor = if self.emit_original_case { 2 } else { 1 };
+                upper.checked_mul(factor)?.checked_add(pending)
+            }),
+        )
+    }
+}
+
+impl<'x, I, R> FusedIterator for OsbTokenizer<'x, I, R>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+}
+
+impl<'x, I, R> OsbTokenizer<'x, I, R>
+where
+    I: DoubleEndedIterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    /// Returns a tokenizer yielding the grams of `OsbTokenizer::new(iter,
+    /// window_size)` most recent first, without collecting the grams.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero or larger than [`MAX_WINDOW_SIZE`].
+    pub fn reversed(iter: I, window_size: usize) -> ReversedOsbTokenizer<'x, I, R> {
+        if let Err(err) = validate_window_size(window_size) {
+            panic!("{err}");
+        }
+
+        ReversedOsbTokenizer {
+            iter,
+            window: VecDeque::with_capacity(window_size),
+            window_size,
+            window_idx: None,
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<'x, I, R> Iterator for ReversedOsbTokenizer<'x, I, R>
+where
+    I: DoubleEndedIterator<Item = Cow<'x, str>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    type Item = OsbToken<R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = match self.window_idx {
+            Some(idx) => idx,
+            None => {
+                self.window.push_front(self.iter.next_back()?);
+                self.window.truncate(self.window_size);
+                self.window.len() - 1
+            }
+        };
+        self.window_idx = idx.checked_sub(1);
+
+        let t1 = self.window[0].as_ref();
+        Some(OsbToken {
+            inner: R::from(if idx != 0 {
+                Gram::Bi {
+                    t1,
+                    t2: self.window[idx].as_ref(),
+                }
+            } else {
+                Gram::Uni { t1 }
+            }),
+            idx,
+        })
+    }
+}
+
+/// An [`OsbTokenizer`] over a fallible source. The grams are emitted as `Ok`
+/// until the source returns an error, which ends the input of the tokenizer:
+/// the grams it can still build from the tokens read before the error are
+/// emitted, followed by the error, after which the tokenizer is exhausted.
+pub struct TryOsbTokenizer<'x, I, E, R>
+where
+    I: Iterator<Item = Result<Cow<'x, str>, E>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    inner: OsbTokenizer<'x, ErrorTrap<I, E>, R>,
+    error: Rc<Cell<Option<E>>>,
+    done: bool,
+}
+
+/// Ends the stream at the first error of the source, keeping the error for
+/// the [`TryOsbTokenizer`] to return.
+struct ErrorTrap<I, E> {
+    iter: I,
+    error: Rc<Cell<Option<E>>>,
+}
+
+impl<'x, I, E, R> TryOsbTokenizer<'x, I, E, R>
+where
+    I: Iterator<Item = Result<Cow<'x, str>, E>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero or larger than [`MAX_WINDOW_SIZE`],
+    /// use [`TryOsbTokenizer::try_new`] for sizes read from configuration.
+    pub fn new(iter: I, window_size: usize) -> Self {
+        match Self::try_new(iter, window_size) {
+            Ok(tokenizer) => tokenizer,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    pub fn try_new(iter: I, window_size: usize) -> Result<Self, OsbError> {
+        let error = Rc::new(Cell::new(None));
+
+        Ok(Self {
+            inner: OsbTokenizer::try_new(
+                ErrorTrap {
+                    iter,
+                    error: error.clone(),
+                },
+                window_size,
+            )?,
+            error,
+            done: false,
+        })
+    }
+}
+
+impl<'x, I, E, R> Iterator for TryOsbTokenizer<'x, I, E, R>
+where
+    I: Iterator<Item = Result<Cow<'x, str>, E>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+    type Item = Result<OsbToken<R>, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // The error may have been read ahead while emitting the last gram
+        if let Some(err) = self.error.take() {
+            self.done = true;
+            return Some(Err(err));
+        }
+
+        match self.inner.next() {
+            Some(token) => Some(Ok(token)),
+            None => {
+                self.done = true;
+                self.error.take().map(Err)
+            }
+        }
+    }
+}
+
+impl<'x, I, E, R> FusedIterator for TryOsbTokenizer<'x, I, E, R>
+where
+    I: Iterator<Item = Result<Cow<'x, str>, E>>,
+    R: for<'y> From<Gram<'y>> + 'static,
+{
+}
+
+impl<'x, I, E> Iterator for ErrorTrap<I, E>
+where
+    I: Iterator<Item = Result<Cow<'x, str>, E>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next()? {
+            Ok(token) => Some(token),
+            Err(err) => {
+                self.error.set(Some(err));
+                None
+            }
+        }
+    }
+}
+
+fn validate_window_size(window_size: usize) -> Result<(), OsbError> {
+    if window_size == 0 {
+        Err(OsbError::ZeroWindowSize)
+    } else if window_size > MAX_WINDOW_SIZE {
+        Err(OsbError::WindowSizeTooLarge(window_size))
+    } else {
+        Ok(())
+    }
+}
+
+impl std::error::Error for OsbError {}
+
+impl Display for OsbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OsbError::ZeroWindowSize => write!(f, "OSB window size must be at least 1"),
+            OsbError::WindowSizeTooLarge(size) => write!(
+                f,
+                "OSB window size {size} exceeds the maximum of {MAX_WINDOW_SIZE}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, collections::HashMap};
+
+    use proptest::{collection::vec, prelude::*};
+    use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+    use crate::{
+        bayes::TokenHash,
+        tokenizers::osb::{
+            Gram, OsbError, OsbToken, TailMode, TokenizerStats, FEATURE_KEY_SEPARATOR,
+            MAX_WINDOW_SIZE,
+        },
+    };
+
+    impl From<Gram<'_>> for String {
+        fn from(value: Gram<'_>) -> Self {
+            value.to_feature_key(b' ')
+        }
+    }
+
+    #[test]
+    fn osb_head_pos() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat";
+        let words = text.split_ascii_whitespace().collect::<Vec<_>>();
+
+        for trigrams in [false, true] {
+            let mut tokenizer =
+                super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 5)
+                    .emit_trigrams(trigrams);
+            assert_eq!(tokenizer.head_pos(), None);
+
+            let mut expected = Vec::new();
+            for (head, word) in words.iter().enumerate() {
+                let grams =
+                    (words.len() - head).min(5) + usize::from(trigrams && words.len() - head >= 3);
+                expected.extend(std::iter::repeat((head, *word)).take(grams));
+            }
+
+            let mut positions = Vec::new();
+            while let Some(token) = tokenizer.next() {
+                let head = tokenizer.head_pos().unwrap();
+                assert!(token.inner.starts_with(words[head]), "{token:?} {head}");
+                positions.push((head, words[head]));
+            }
+            assert_eq!(positions, expected);
+            assert_eq!(positions.len(), if trigrams { 66 } else { 55 });
+        }
+    }
+
+    #[test]
+    fn gram_ordering() {
+        let mut grams = vec![
+            Gram::Tri {
+                t1: "a",
+                t2: "b",
+                t3: "c",
+            },
+            Gram::Bi { t1: "b", t2: "a" },
+            Gram::Uni { t1: "zebra" },
+            Gram::Bi { t1: "a", t2: "z" },
+            Gram::Uni { t1: "apple" },
+            Gram::Bi { t1: "a", t2: "b" },
+            Gram::Uni { t1: "Zebra" },
+        ];
+        grams.sort();
+
+        assert_eq!(
+            grams,
+            [
+                Gram::Uni { t1: "Zebra" },
+                Gram::Uni { t1: "apple" },
+                Gram::Uni { t1: "zebra" },
+                Gram::Bi { t1: "a", t2: "b" },
+                Gram::Bi { t1: "a", t2: "z" },
+                Gram::Bi { t1: "b", t2: "a" },
+                Gram::Tri {
+                    t1: "a",
+                    t2: "b",
+                    t3: "c",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn osb_redacted() {
+        let tokens = super::OsbTokenizer::<_, String>::new(
+            "secret password secret".split(' ').map(Cow::from),
+            2,
+        )
+        .collect::<Vec<_>>();
+
+        for token in &tokens {
+            for formatted in [
+                format!("{:?}", token.redacted()),
+                format!("{}", token.redacted()),
+            ] {
+                assert!(!formatted.contains("secret"), "{formatted}");
+                assert!(!formatted.contains("password"), "{formatted}");
+                assert!(formatted.contains(&format!("len={}", token.inner.len())));
+            }
+        }
+        assert_eq!(
+            format!("{:?}", tokens[1].redacted()),
+            format!(
+                "OsbToken {{ inner: <redacted len=15 hash={:016x}>, idx: 1 }}",
+                xxhash_rust::xxh3::xxh3_64("secret password".as_bytes())
+            )
+        );
+        assert!(format!("{}", tokens[1].redacted()).ends_with(" idx=1"));
+
+        // Equal tokens can still be correlated
+        assert_eq!(
+            format!("{}", tokens[0].redacted()),
+            format!("{}", tokens[4].redacted())
+        );
+        assert_ne!(
+            format!("{}", tokens[0].redacted()),
+            format!("{}", tokens[2].redacted())
+        );
+    }
+
+    #[test]
+    fn osb_prefill() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat";
+        let tokenizer = || {
+            super::OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5)
+        };
+        let expected = tokenizer().collect::<Vec<_>>();
+
+        let mut prefilled = tokenizer();
+        prefilled.prefill();
+        prefilled.prefill();
+        assert_eq!(
+            prefilled.current_window().collect::<Vec<_>>(),
+            [
+                Some("The"),
+                Some("quick"),
+                Some("brown"),
+                Some("fox"),
+                Some("jumps")
+            ]
+        );
+        let mut tokens = prefilled.by_ref().take(7).collect::<Vec<_>>();
+        prefilled.prefill();
+        tokens.extend(prefilled.by_ref());
+        prefilled.prefill();
+        assert_eq!(prefilled.next(), None);
+        assert_eq!(tokens, expected);
+
+        // Empty source
+        let mut empty = super::OsbTokenizer::<_, String>::new(std::iter::empty(), 5);
+        empty.prefill();
+        empty.prefill();
+        assert_eq!(empty.current_window().collect::<Vec<_>>(), [None; 5]);
+        assert_eq!(empty.next(), None);
+    }
+
+    #[test]
+    fn osb_feature_key() {
+        let spaced = Gram::Bi {
+            t1: "new york",
+            t2: "city",
+        };
+        let split = Gram::Bi {
+            t1: "new",
+            t2: "york city",
+        };
+
+        assert_eq!(spaced.to_feature_key(b' '), split.to_feature_key(b' '));
+        assert_ne!(
+            spaced.to_feature_key(FEATURE_KEY_SEPARATOR),
+            split.to_feature_key(FEATURE_KEY_SEPARATOR)
+        );
+        assert_eq!(
+            spaced.to_feature_key(FEATURE_KEY_SEPARATOR),
+            "new york\0city"
+        );
+        assert_eq!(
+            Gram::Tri {
+                t1: "a",
+                t2: "b c",
+                t3: "d"
+            }
+            .to_feature_key(FEATURE_KEY_SEPARATOR),
+            "a\0b c\0d"
+        );
+        assert_eq!(Gram::Uni { t1: "a b" }.to_feature_key(b'|'), "a b");
+    }
+
+    #[test]
+    fn osb_tail_mode() {
+        let words = "The quick brown fox jumps over the lazy dog and the lazy cat"
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>();
+        let tokenize = |tail_mode| {
+            super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 5)
+                .tail_mode(tail_mode)
+                .map(|t| (t.inner, t.idx))
+                .collect::<Vec<_>>()
+        };
+        fn tail(tokens: &[(String, usize)], n: usize) -> Vec<(&str, usize)> {
+            tokens[tokens.len() - n..]
+                .iter()
+                .map(|(gram, idx)| (gram.as_str(), *idx))
+                .collect()
+        }
+
+        let shrink = tokenize(TailMode::Shrink);
+        assert_eq!(shrink.len(), 55);
+        assert_eq!(
+            tail(&shrink, 6),
+            [
+                ("the", 0),
+                ("the lazy", 1),
+                ("the cat", 2),
+                ("lazy", 0),
+                ("lazy cat", 1),
+                ("cat", 0)
+            ]
+        );
+        assert_eq!(
+            shrink,
+            super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 5)
+                .map(|t| (t.inner, t.idx))
+                .collect::<Vec<_>>()
+        );
+
+        let pad = tokenize(TailMode::Pad);
+        assert_eq!(pad.len(), 65);
+        assert_eq!(
+            tail(&pad, 10),
+            [
+                ("lazy", 0),
+                ("lazy cat", 1),
+                ("lazy __end__", 2),
+                ("lazy __end__", 3),
+                ("lazy __end__", 4),
+                ("cat", 0),
+                ("cat __end__", 1),
+                ("cat __end__", 2),
+                ("cat __end__", 3),
+                ("cat __end__", 4)
+            ]
+        );
+        assert_eq!(pad[..45], shrink[..45]);
 
-pub struct OsbTokenizer<'x, I, R>
-where
-    I: Iterator<Item = Cow<'x, str>>,
-    R: for<'y> From<Gram<'y>> + 'static,
-{
-    iter: Peekable<I>,
-    buf: Vec<Option<Cow<'x, str>>>,
-    window_size: usize,
-    window_pos: usize,
-    window_idx: usize,
-    phantom: std::marker::PhantomData<R>,
-}
+        let drop = tokenize(TailMode::Drop);
+        assert_eq!(drop.len(), 45);
+        assert_eq!(
+            tail(&drop, 5),
+            [
+                ("dog", 0),
+                ("dog and", 1),
+                ("dog the", 2),
+                ("dog lazy", 3),
+                ("dog cat", 4)
+            ]
+        );
+        assert_eq!(drop[..], shrink[..45]);
 
-impl<'x, I, R> OsbTokenizer<'x, I, R>
-where
-    I: Iterator<Item = Cow<'x, str>>,
-    R: for<'y> From<Gram<'y>> + 'static,
-{
-    pub fn new(iter: I, window_size: usize) -> Self {
-        Self {
-            iter: iter.peekable(),
-            buf: vec![None; window_size],
-            window_pos: 0,
-            window_idx: 0,
-            window_size,
-            phantom: std::marker::PhantomData,
+        // Padded windows count towards the size hint and the limit
+        for (trigrams, expected) in [(false, 65), (true, 76)] {
+            let mut tokenizer =
+                super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 5)
+                    .tail_mode(TailMode::Pad)
+                    .emit_trigrams(trigrams);
+            let mut remaining = expected;
+            assert_eq!(tokenizer.size_hint(), (remaining, Some(remaining)));
+            while tokenizer.next().is_some() {
+                remaining -= 1;
+                assert_eq!(tokenizer.size_hint(), (remaining, Some(remaining)));
+            }
+            assert_eq!(remaining, 0);
         }
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::with_limit(
+                words[10..].iter().copied().map(Cow::from),
+                5,
+                12
+            )
+            .tail_mode(TailMode::Pad)
+            .count(),
+            10
+        );
     }
-}
 
-impl<'x, I, R> Iterator for OsbTokenizer<'x, I, R>
-where
-    I: Iterator<Item = Cow<'x, str>>,
-    R: for<'y> From<Gram<'y>> + 'static,
-{
-    type Item = OsbToken<R>;
+    #[test]
+    fn osb_flush_partial() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat";
+        let tokenize = |flush_partial| {
+            super::OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5)
+                .flush_partial(flush_partial)
+                .collect::<Vec<_>>()
+        };
+        let flushed = tokenize(true);
+        let suppressed = tokenize(false);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let end_pos = (self.window_pos + self.window_idx) % self.window_size;
-        if self.buf[end_pos].is_none() {
-            self.buf[end_pos] = self.iter.next();
+        assert_eq!(
+            flushed[flushed.len() - 3..]
+                .iter()
+                .map(|t| t.inner.as_str())
+                .collect::<Vec<_>>(),
+            ["lazy", "lazy cat", "cat"]
+        );
+        assert_eq!(
+            suppressed[suppressed.len() - 3..]
+                .iter()
+                .map(|t| t.inner.as_str())
+                .collect::<Vec<_>>(),
+            ["dog the", "dog lazy", "dog cat"]
+        );
+        assert_eq!(suppressed, flushed[..suppressed.len()]);
+        assert_eq!(suppressed.len(), 9 * 5);
+
+        // Inputs shorter than the window emit nothing
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::new(["a", "b"].into_iter().map(Cow::from), 5)
+                .flush_partial(false)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn osb_next_str() {
+        let text = "The quick brown fox jumps over the lazy dog and the lazy cat ".repeat(50);
+        let allocated =
+            super::OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5)
+                .collect::<Vec<_>>();
+
+        let mut tokenizer =
+            super::OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 5);
+        let mut scratch = String::new();
+        let mut grams = Vec::with_capacity(allocated.len());
+        while let Some(token) = tokenizer.next_str(&mut scratch) {
+            let expected = &allocated[grams.len()];
+            assert_eq!(
+                (token.inner, token.idx),
+                (expected.inner.as_str(), expected.idx)
+            );
+            grams.push(token.idx);
         }
 
-        let t1 = self.buf[self.window_pos % self.window_size].as_deref()?;
-        let token = OsbToken {
-            inner: R::from(if self.window_idx != 0 {
-                Gram::Bi {
-                    t1,
-                    t2: self.buf[end_pos].as_deref()?,
-                }
+        assert_eq!(grams.len(), allocated.len());
+        assert!(scratch.capacity() < 32);
+    }
+
+    #[test]
+    fn osb_window_size() {
+        let tokens = || {
+            "The quick brown fox"
+                .split_ascii_whitespace()
+                .map(Cow::from)
+        };
+
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::try_new(tokens(), 0).err(),
+            Some(OsbError::ZeroWindowSize)
+        );
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::try_new(tokens(), MAX_WINDOW_SIZE + 1).err(),
+            Some(OsbError::WindowSizeTooLarge(MAX_WINDOW_SIZE + 1))
+        );
+
+        // A window of one only emits unigrams
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::try_new(tokens(), 1)
+                .unwrap()
+                .map(|t| (t.inner, t.idx))
+                .collect::<Vec<_>>(),
+            vec![
+                ("The".to_string(), 0),
+                ("quick".to_string(), 0),
+                ("brown".to_string(), 0),
+                ("fox".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn osb_reset_with_window() {
+        let words = "The quick brown fox jumps over the lazy dog and the lazy cat"
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>();
+        let tokens = || words.iter().copied().map(Cow::from);
+        let tokenize = |window_size| {
+            super::OsbTokenizer::<_, String>::new(tokens(), window_size).collect::<Vec<_>>()
+        };
+
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(tokens(), 5);
+        let capacity = tokenizer.buf.capacity();
+        assert_eq!(tokenizer.by_ref().count(), 55);
+
+        // Smaller windows reuse the buffer
+        tokenizer.reset_with_window(tokens(), 2).unwrap();
+        assert_eq!(tokenizer.buf.capacity(), capacity);
+        let grams = tokenizer.by_ref().collect::<Vec<_>>();
+        assert_eq!(grams.len(), 25);
+        assert_eq!(grams, tokenize(2));
+
+        // Resetting mid-stream restarts from the new iterator
+        tokenizer.reset_with_window(tokens(), 4).unwrap();
+        tokenizer.next();
+        tokenizer.reset_with_window(tokens(), 5).unwrap();
+        assert_eq!(tokenizer.by_ref().collect::<Vec<_>>(), tokenize(5));
+
+        assert_eq!(
+            tokenizer.reset_with_window(tokens(), 0),
+            Err(OsbError::ZeroWindowSize)
+        );
+    }
+
+    #[test]
+    fn osb_fold_case() {
+        let tokens = || {
+            ["The", "cat", "saw", "the", "Cat"]
+                .into_iter()
+                .map(Cow::from)
+        };
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(tokens(), 2).fold_case(true);
+        let mut grams = Vec::new();
+        while let Some(token) =
+            tokenizer.next_cased_gram(|key, original| (key.hash64(0), String::from(original)))
+        {
+            grams.push(token.inner);
+        }
+
+        // "The" and "the", "The cat" and "the Cat"
+        for (a, b) in [(0, 6), (1, 7), (2, 8)] {
+            assert_eq!(grams[a].0, grams[b].0);
+            assert_ne!(grams[a].1, grams[b].1);
+        }
+        assert_eq!(grams[1].0, xxh3_64_with_seed(b"the cat", 0));
+        assert_eq!(grams[7].1, "the Cat");
+
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::new(tokens(), 2)
+                .fold_case(true)
+                .map(|token| token.inner)
+                .collect::<Vec<_>>(),
+            ["the", "the cat", "cat", "cat saw", "saw", "saw the", "the", "the cat", "cat"]
+        );
+        assert_ne!(
+            super::OsbTokenizer::<_, u64>::new(tokens(), 2).next(),
+            super::OsbTokenizer::<_, u64>::new(tokens().skip(3), 2).next()
+        );
+    }
+
+    #[test]
+    fn osb_emit_original_case() {
+        fn window<'x, I: Iterator<Item = Cow<'x, str>>>(
+            tokenizer: &super::OsbTokenizer<'x, I, String>,
+        ) -> Vec<Option<String>> {
+            tokenizer
+                .current_window()
+                .map(|token| token.map(String::from))
+                .collect()
+        }
+        let tokens = || {
+            ["The", "cat", "saw", "the", "Cat"]
+                .into_iter()
+                .map(Cow::from)
+        };
+
+        let mut tokenizer =
+            super::OsbTokenizer::<_, String>::new(tokens(), 2).emit_original_case(true);
+        assert_eq!(tokenizer.size_hint(), (9, Some(18)));
+        let mut grams = Vec::new();
+        let mut windows = Vec::new();
+        while let Some(token) = tokenizer.next() {
+            if token.inner.chars().any(char::is_uppercase) {
+                // Original case grams don't move the window
+                assert_eq!(windows.last(), Some(&window(&tokenizer)));
             } else {
-                Gram::Uni { t1 }
-            }),
-            idx: self.window_idx,
+                windows.push(window(&tokenizer));
+            }
+            grams.push((token.inner, token.idx));
+        }
+        assert_eq!(
+            grams
+                .iter()
+                .map(|(gram, idx)| (gram.as_str(), *idx))
+                .collect::<Vec<_>>(),
+            [
+                ("the", 0),
+                ("The", 0),
+                ("the cat", 1),
+                ("The cat", 1),
+                ("cat", 0),
+                ("cat saw", 1),
+                ("saw", 0),
+                ("saw the", 1),
+                ("the", 0),
+                ("the cat", 1),
+                ("the Cat", 1),
+                ("cat", 0),
+                ("Cat", 0),
+            ]
+        );
+
+        let mut folded = super::OsbTokenizer::<_, String>::new(tokens(), 2).fold_case(true);
+        let mut expected = Vec::new();
+        while folded.next().is_some() {
+            expected.push(window(&folded));
+        }
+        assert_eq!(windows, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn osb_token_serde() {
+        let tokens = super::OsbTokenizer::<_, String>::new(
+            "The quick brown fox"
+                .split_ascii_whitespace()
+                .map(Cow::from),
+            3,
+        )
+        .collect::<Vec<_>>();
+
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert!(json.starts_with(r#"[{"inner":"The","idx":0},{"inner":"The quick","idx":1}"#));
+        assert_eq!(
+            serde_json::from_str::<Vec<OsbToken<String>>>(&json).unwrap(),
+            tokens
+        );
+    }
+
+    #[test]
+    fn osb_current_window() {
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(
+            ["a", "b", "c", "d", "e"].into_iter().map(Cow::from),
+            3,
+        );
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [None, None, None]
+        );
+
+        // Tokens are read as the bigrams reach them
+        assert_eq!(tokenizer.next().unwrap().inner, "a");
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [Some("a"), None, None]
+        );
+        assert_eq!(tokenizer.next().unwrap().inner, "a b");
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [Some("a"), Some("b"), None]
+        );
+
+        // The window slides once the head token emitted all of its grams
+        assert_eq!(tokenizer.next().unwrap().inner, "a c");
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [Some("b"), Some("c"), None]
+        );
+        assert_eq!(tokenizer.nth(2).unwrap().inner, "b d");
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [Some("c"), Some("d"), None]
+        );
+
+        tokenizer.by_ref().count();
+        assert_eq!(
+            tokenizer.current_window().collect::<Vec<_>>(),
+            [None, None, None]
+        );
+    }
+
+    #[test]
+    fn try_osb_tokenizer() {
+        let source = [Ok("a"), Ok("b"), Err("read failed"), Ok("d")]
+            .into_iter()
+            .map(|token| token.map(Cow::from));
+        let mut tokenizer = super::TryOsbTokenizer::<_, _, String>::new(source, 2);
+
+        // The grams built before the error are emitted, then the error
+        assert_eq!(
+            tokenizer
+                .by_ref()
+                .map(|token| token.map(|token| token.inner))
+                .collect::<Vec<_>>(),
+            [
+                Ok("a".to_string()),
+                Ok("a b".to_string()),
+                Ok("b".to_string()),
+                Err("read failed")
+            ]
+        );
+        assert!(tokenizer.next().is_none());
+
+        // Sources without errors match the infallible tokenizer
+        let words = ["The", "quick", "brown", "fox"];
+        assert_eq!(
+            super::TryOsbTokenizer::<_, &str, String>::new(
+                words.iter().map(|word| Ok(Cow::from(*word))),
+                3
+            )
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+            super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 3)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn osb_stats() {
+        let tokens = || {
+            "The quick brown fox jumps over the lazy dog and the lazy cat"
+                .split_ascii_whitespace()
+                .map(Cow::from)
         };
 
-        // Increment window index
-        self.window_idx += 1;
-        if self.window_idx == self.window_size
-            || (self.iter.peek().is_none()
-                && self.buf[(self.window_pos + self.window_idx) % self.window_size].is_none())
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(tokens(), 5).collect_stats(true);
+        assert_eq!(tokenizer.by_ref().count(), 55);
+        assert_eq!(
+            tokenizer.stats(),
+            Some(&TokenizerStats {
+                grams: 55,
+                unigrams: 13,
+                bigrams: 42,
+                trigrams: 0,
+                truncated_windows: 4,
+            })
+        );
+
+        // Resetting starts counting from scratch
+        tokenizer.reset_with_window(tokens(), 2).unwrap();
+        assert_eq!(tokenizer.stats(), Some(&TokenizerStats::default()));
+        assert_eq!(tokenizer.by_ref().count(), 25);
+        assert_eq!(
+            tokenizer.stats(),
+            Some(&TokenizerStats {
+                grams: 25,
+                unigrams: 13,
+                bigrams: 12,
+                trigrams: 0,
+                truncated_windows: 1,
+            })
+        );
+
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(tokens(), 5)
+            .emit_trigrams(true)
+            .collect_stats(true);
+        let grams = tokenizer.by_ref().count();
+        let stats = tokenizer.stats().unwrap();
+        assert_eq!(stats.trigrams, 11);
+        assert_eq!(stats.grams, grams);
+        assert_eq!(stats.unigrams + stats.bigrams + stats.trigrams, grams);
+
+        // Not collected unless requested
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(tokens(), 5);
+        tokenizer.by_ref().count();
+        assert_eq!(tokenizer.stats(), None);
+    }
+
+    #[test]
+    fn osb_fused() {
+        // Yields a token, then None, then the next token and so on
+        struct Flaky<'x>(std::slice::Iter<'x, &'x str>, bool);
+        impl<'x> Iterator for Flaky<'x> {
+            type Item = Cow<'x, str>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.1 = !self.1;
+                if self.1 {
+                    self.0.next().map(|token| Cow::from(*token))
+                } else {
+                    None
+                }
+            }
+        }
+        let words = ["a", "b", "c", "d"];
+
+        for (flush_partial, trigrams) in
+            [(true, false), (false, false), (true, true), (false, true)]
         {
-            self.buf[self.window_pos % self.window_size] = None;
-            self.window_idx = 0;
-            self.window_pos += 1;
+            let mut tokenizer =
+                super::OsbTokenizer::<_, String>::new(words.iter().copied().map(Cow::from), 3)
+                    .flush_partial(flush_partial)
+                    .emit_trigrams(trigrams);
+            assert_ne!(tokenizer.by_ref().count(), 0);
+            for _ in 0..5 {
+                assert_eq!(tokenizer.next(), None);
+            }
         }
 
-        Some(token)
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(Flaky(words.iter(), false), 3);
+        assert_eq!(
+            tokenizer.by_ref().map(|t| t.inner).collect::<Vec<_>>(),
+            ["a"]
+        );
+        for _ in 0..5 {
+            assert_eq!(tokenizer.next(), None);
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::borrow::Cow;
+    #[test]
+    fn osb_with_limit() {
+        let words = "The quick brown fox jumps over the lazy dog"
+            .split_ascii_whitespace()
+            .map(Cow::from)
+            .collect::<Vec<_>>();
+        let unlimited =
+            super::OsbTokenizer::<_, String>::new(words.clone().into_iter(), 3).collect::<Vec<_>>();
 
-    use crate::tokenizers::osb::{Gram, OsbToken};
+        for max_grams in [0, 1, 2, 3, 4, 7, 10, 20, 100] {
+            let mut tokenizer = super::OsbTokenizer::<_, String>::with_limit(
+                words.clone().into_iter(),
+                3,
+                max_grams,
+            );
+            let limited = tokenizer.by_ref().collect::<Vec<_>>();
 
-    impl From<Gram<'_>> for String {
-        fn from(value: Gram<'_>) -> Self {
-            match value {
-                Gram::Uni { t1 } => t1.to_string(),
-                Gram::Bi { t1, t2 } => format!("{t1} {t2}"),
+            assert!(limited.len() <= max_grams);
+            assert_eq!(limited[..], unlimited[..limited.len()]);
+            assert!(
+                unlimited
+                    .get(limited.len())
+                    .map_or(true, |next| next.idx == 0),
+                "{max_grams}: {limited:?}"
+            );
+            assert!(max_grams - limited.len() < 3 || limited.len() == unlimited.len());
+            assert_eq!(tokenizer.next(), None);
+        }
+
+        let trigrams = super::OsbTokenizer::<_, String>::with_limit(words.into_iter(), 3, 8)
+            .emit_trigrams(true)
+            .collect::<Vec<_>>();
+        assert_eq!(trigrams.len(), 8);
+        assert_eq!(trigrams.last().unwrap().idx, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "OSB window size must be at least 1")]
+    fn osb_zero_window_panics() {
+        super::OsbTokenizer::<_, String>::new(["a"].into_iter().map(Cow::from), 0);
+    }
+
+    #[test]
+    fn gram_hash64() {
+        let uni = Gram::Uni { t1: "foo" };
+        let bi = Gram::Bi {
+            t1: "foo",
+            t2: "bar",
+        };
+
+        // Stable across runs and platforms
+        assert_eq!(uni.hash64(0), xxh3_64_with_seed(b"foo", 0));
+        assert_eq!(bi.hash64(42), xxh3_64_with_seed(b"foo bar", 42));
+        assert_eq!(
+            Gram::Tri {
+                t1: "foo",
+                t2: "bar",
+                t3: "baz"
+            }
+            .hash64(7),
+            xxh3_64_with_seed(b"foo bar baz", 7)
+        );
+
+        assert_ne!(uni.hash64(0), bi.hash64(0));
+        assert_ne!(bi.hash64(0), bi.hash64(1));
+        assert_eq!(u64::from(bi.clone()), TokenHash::from(bi).h1);
+
+        let grams = super::OsbTokenizer::<_, u64>::new(
+            "foo bar foo".split_ascii_whitespace().map(Cow::from),
+            2,
+        )
+        .map(|t| t.inner)
+        .collect::<Vec<_>>();
+        assert_eq!(grams[0], grams[4]);
+        assert_eq!(grams[0], u64::from(Gram::Uni { t1: "foo" }));
+    }
+
+    #[test]
+    fn gram_pairs() {
+        let counts = HashMap::from([
+            (("buy", None), 1),
+            (("buy", Some("cheap")), 2),
+            (("buy", Some("pills")), 3),
+            (("pills", None), 4),
+        ]);
+
+        let mut tokenizer = super::OsbTokenizer::<_, String>::new(
+            "buy cheap pills".split_ascii_whitespace().map(Cow::from),
+            3,
+        );
+        let mut found = Vec::new();
+        while let Some(token) = tokenizer.next_gram(|gram| {
+            <(&str, Option<&str>)>::try_from(gram)
+                .ok()
+                .and_then(|pair| counts.get(&pair).copied())
+        }) {
+            found.push((token.inner, token.idx));
+        }
+        assert_eq!(
+            found,
+            vec![
+                (Some(1), 0),
+                (Some(2), 1),
+                (Some(3), 2),
+                (None, 0),
+                (None, 1),
+                (Some(4), 0),
+            ]
+        );
+
+        assert_eq!(
+            <(&str, Option<&str>)>::try_from(Gram::Tri {
+                t1: "buy",
+                t2: "cheap",
+                t3: "pills"
+            }),
+            Err(Gram::Tri {
+                t1: "buy",
+                t2: "cheap",
+                t3: "pills"
+            })
+        );
+    }
+
+    #[test]
+    fn osb_size_hint() {
+        let words = "The quick brown fox jumps over the lazy dog and the lazy cat"
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>();
+
+        for (flush_partial, trigrams, expected) in [
+            (true, false, 55),
+            (false, false, 45),
+            (true, true, 66),
+            (false, true, 54),
+        ] {
+            let mut tokenizer =
+                super::OsbTokenizer::<_, String>::new(words.clone().into_iter().map(Cow::from), 5)
+                    .flush_partial(flush_partial)
+                    .emit_trigrams(trigrams);
+            assert_eq!(tokenizer.size_hint(), (expected, Some(expected)));
+
+            // The hint stays exact while the stream is consumed
+            let mut remaining = expected;
+            while tokenizer.next().is_some() {
+                remaining -= 1;
+                assert_eq!(
+                    tokenizer.size_hint(),
+                    (remaining, Some(remaining)),
+                    "flush_partial {flush_partial}, trigrams {trigrams}"
+                );
             }
+            assert_eq!(remaining, 0);
+        }
+
+        assert_eq!(
+            super::OsbTokenizer::<_, String>::new(words.into_iter().map(Cow::from), 5)
+                .collect::<Vec<_>>()
+                .capacity(),
+            55
+        );
+    }
+
+    #[test]
+    fn osb_reversed() {
+        for (text, window_size) in [
+            (
+                "The quick brown fox jumps over the lazy dog and the lazy cat",
+                5,
+            ),
+            ("The quick brown", 5),
+            ("The quick brown fox", 1),
+            ("", 3),
+        ] {
+            let tokens = || text.split_ascii_whitespace().map(Cow::from);
+            let mut forward =
+                super::OsbTokenizer::<_, String>::new(tokens(), window_size).collect::<Vec<_>>();
+            forward.reverse();
+
+            assert_eq!(
+                super::OsbTokenizer::<_, String>::reversed(tokens(), window_size)
+                    .collect::<Vec<_>>(),
+                forward,
+                "failed for {text:?}"
+            );
         }
+
+        let mut reversed = super::OsbTokenizer::<_, String>::reversed(
+            ["a", "b", "c"].into_iter().map(Cow::from),
+            2,
+        );
+        assert_eq!(
+            reversed.next(),
+            Some(OsbToken {
+                inner: "c".to_string(),
+                idx: 0
+            })
+        );
+        assert_eq!(
+            reversed.next(),
+            Some(OsbToken {
+                inner: "b c".to_string(),
+                idx: 1
+            })
+        );
+    }
+
+    #[test]
+    fn osb_trigrams() {
+        let text = "The quick brown fox jumps";
+        let tokenize = |trigrams| {
+            super::OsbTokenizer::<_, String>::new(text.split_ascii_whitespace().map(Cow::from), 3)
+                .emit_trigrams(trigrams)
+                .collect::<Vec<_>>()
+        };
+        let with_trigrams = tokenize(true);
+
+        assert_eq!(
+            with_trigrams
+                .iter()
+                .map(|t| (t.inner.as_str(), t.idx))
+                .collect::<Vec<_>>(),
+            vec![
+                ("The", 0),
+                ("The quick", 1),
+                ("The brown", 2),
+                ("The quick brown", 3),
+                ("quick", 0),
+                ("quick brown", 1),
+                ("quick fox", 2),
+                ("quick brown fox", 3),
+                ("brown", 0),
+                ("brown fox", 1),
+                ("brown jumps", 2),
+                ("brown fox jumps", 3),
+                ("fox", 0),
+                ("fox jumps", 1),
+                ("jumps", 0),
+            ]
+        );
+
+        // Unigram and bigram indexes are unchanged
+        assert_eq!(
+            with_trigrams
+                .into_iter()
+                .filter(|t| t.idx < 3)
+                .collect::<Vec<_>>(),
+            tokenize(false)
+        );
+
+        // Windows too small for trigrams
+        assert!(super::OsbTokenizer::<_, String>::new(
+            text.split_ascii_whitespace().map(Cow::from),
+            2
+        )
+        .emit_trigrams(true)
+        .all(|t| t.idx < 2));
     }
 
     #[test]
@@ -355,4 +2165,56 @@ mod test {
             ]
         );
     }
+
+    proptest! {
+        #[test]
+        fn osb_invariants(
+            words in vec("[a-z]{1,8}", 0..100),
+            window_size in 1..=MAX_WINDOW_SIZE,
+            flush_partial in any::<bool>(),
+        ) {
+            let mut tokenizer = super::OsbTokenizer::<_, String>::new(
+                words.iter().map(|word| Cow::from(word.as_str())),
+                window_size,
+            )
+            .flush_partial(flush_partial);
+            let mut grams = Vec::new();
+            while let Some(token) = tokenizer
+                .next_gram(|gram| (matches!(gram, Gram::Uni { .. }), String::from(gram)))
+            {
+                grams.push(token);
+            }
+
+            // Every head token emits a full window, and only the last
+            // `window_size - 1` ones emit shorter windows when flushed
+            let n = words.len();
+            let heads = if flush_partial { n } else { (n + 1).saturating_sub(window_size) };
+            let expected = if !flush_partial {
+                heads * window_size
+            } else if n < window_size {
+                n * (n + 1) / 2
+            } else {
+                n * window_size - window_size * (window_size - 1) / 2
+            };
+            prop_assert_eq!(grams.len(), expected);
+
+            // Head tokens start with their unigram followed by the bigrams
+            // in increasing distance
+            let mut head = 0;
+            for (pos, token) in grams.iter().enumerate() {
+                let (is_unigram, gram) = &token.inner;
+                prop_assert!(token.idx < window_size);
+                prop_assert_eq!(*is_unigram, token.idx == 0);
+                if token.idx == 0 {
+                    prop_assert_eq!(gram, &words[head]);
+                    head += 1;
+                } else {
+                    prop_assert_eq!(token.idx, grams[pos - 1].idx + 1);
+                    prop_assert_eq!(gram, &format!("{} {}", words[head - 1], words[head - 1 + token.idx]));
+                }
+            }
+            prop_assert_eq!(head, heads);
+            prop_assert!(tokenizer.next().is_none());
+        }
+    }
 }
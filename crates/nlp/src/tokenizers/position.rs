@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+/// Coarse position of a token within its stream, by thirds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionBucket {
+    Beginning,
+    Middle,
+    End,
+}
+
+/// Prefixes each token with the bucket of its position in the stream
+/// (`beg:unsubscribe`, `mid:...`, `end:...`), so that the same phrase
+/// produces different grams depending on where it appears.
+pub struct PositionTagger<I> {
+    iter: I,
+    pos: usize,
+    len: usize,
+}
+
+impl PositionBucket {
+    pub fn from_position(pos: usize, len: usize) -> Self {
+        if pos * 3 < len {
+            PositionBucket::Beginning
+        } else if pos * 3 < len * 2 {
+            PositionBucket::Middle
+        } else {
+            PositionBucket::End
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            PositionBucket::Beginning => "beg:",
+            PositionBucket::Middle => "mid:",
+            PositionBucket::End => "end:",
+        }
+    }
+}
+
+impl<'x> PositionTagger<std::vec::IntoIter<Cow<'x, str>>> {
+    /// Buffers the whole stream to tag tokens by their exact position.
+    pub fn new(iter: impl IntoIterator<Item = Cow<'x, str>>) -> Self {
+        let tokens = iter.into_iter().collect::<Vec<_>>();
+        PositionTagger {
+            len: tokens.len(),
+            iter: tokens.into_iter(),
+            pos: 0,
+        }
+    }
+}
+
+impl<'x, I> PositionTagger<I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    /// Tags tokens as they are read using an estimate of the stream length,
+    /// such as the text length divided by the average token length. Tokens
+    /// past the estimate are tagged as `end:`.
+    pub fn with_len_hint(iter: I, len_hint: usize) -> Self {
+        PositionTagger {
+            iter,
+            pos: 0,
+            len: len_hint,
+        }
+    }
+}
+
+impl<'x, I> Iterator for PositionTagger<I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.iter.next()?;
+        let bucket = PositionBucket::from_position(self.pos, self.len);
+        self.pos += 1;
+
+        Some(format!("{}{token}", bucket.prefix()).into())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{
+        bayes::TokenHash,
+        tokenizers::osb::{Gram, OsbTokenizer},
+    };
+
+    use super::{PositionBucket, PositionTagger};
+
+    #[test]
+    fn position_buckets() {
+        let text = "unsubscribe now to claim your prize money before friday unsubscribe";
+        let tokens = || text.split_ascii_whitespace().map(Cow::from);
+        let expected = vec![
+            "beg:unsubscribe",
+            "beg:now",
+            "beg:to",
+            "beg:claim",
+            "mid:your",
+            "mid:prize",
+            "mid:money",
+            "end:before",
+            "end:friday",
+            "end:unsubscribe",
+        ];
+
+        // Two-pass and an exact hint produce the same buckets
+        assert_eq!(PositionTagger::new(tokens()).collect::<Vec<_>>(), expected);
+        assert_eq!(
+            PositionTagger::with_len_hint(tokens(), 10).collect::<Vec<_>>(),
+            expected
+        );
+
+        // Underestimated streams tag the overflow as the end
+        assert_eq!(
+            PositionTagger::with_len_hint(tokens(), 3)
+                .map(|t| t[..4].to_string())
+                .collect::<Vec<_>>(),
+            ["beg:", "mid:", "end:", "end:", "end:", "end:", "end:", "end:", "end:", "end:"]
+        );
+        assert_eq!(
+            PositionBucket::from_position(0, 0),
+            PositionBucket::End,
+            "empty hint"
+        );
+
+        // The same word at the start and the end yields distinct grams
+        let grams = OsbTokenizer::<_, TokenHash>::new(PositionTagger::new(tokens()), 5)
+            .map(|t| t.inner)
+            .collect::<Vec<_>>();
+        let beg = TokenHash::from(Gram::Uni {
+            t1: "beg:unsubscribe",
+        });
+        let end = TokenHash::from(Gram::Uni {
+            t1: "end:unsubscribe",
+        });
+        assert_ne!(beg, end);
+        assert!(grams.contains(&beg) && grams.contains(&end));
+    }
+}
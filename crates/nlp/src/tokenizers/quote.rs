@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Text written by the sender of the message.
+    New,
+    /// Quoted text, with its nesting level (`>` is 1, `>>` is 2).
+    Quoted(usize),
+    /// A signature, from a `-- ` separator up to the next quoted line.
+    Signature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'x> {
+    pub kind: SegmentKind,
+    /// The lines of the segment, including quote markers and line breaks.
+    pub text: &'x str,
+}
+
+/// Splits a plain text email body into runs of consecutive lines of the
+/// same `SegmentKind`, so that quoted replies and signatures can be left
+/// out before tokenizing.
+pub struct QuoteSplitter<'x> {
+    text: &'x str,
+    pos: usize,
+    in_signature: bool,
+}
+
+impl<'x> QuoteSplitter<'x> {
+    pub fn new(text: &'x str) -> Self {
+        QuoteSplitter {
+            text,
+            pos: 0,
+            in_signature: false,
+        }
+    }
+
+    /// Returns only the text written by the sender, to be tokenized
+    /// segment by segment.
+    pub fn new_text(self) -> impl Iterator<Item = &'x str> {
+        self.filter_map(|segment| (segment.kind == SegmentKind::New).then_some(segment.text))
+    }
+
+    fn line_kind(&mut self, line: &str) -> SegmentKind {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let level = quote_level(line);
+
+        if level > 0 {
+            self.in_signature = false;
+            SegmentKind::Quoted(level)
+        } else if line == "-- " || line == "--" {
+            self.in_signature = true;
+            SegmentKind::Signature
+        } else if self.in_signature {
+            SegmentKind::Signature
+        } else {
+            SegmentKind::New
+        }
+    }
+}
+
+impl<'x> Iterator for QuoteSplitter<'x> {
+    type Item = Segment<'x>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let text = self.text;
+        let start = self.pos;
+        let mut kind = None;
+
+        while self.pos < text.len() {
+            let line_end = text[self.pos..]
+                .find('\n')
+                .map_or(text.len(), |end| self.pos + end + 1);
+            let line_kind = self.line_kind(&text[self.pos..line_end]);
+
+            match kind {
+                None => kind = Some(line_kind),
+                Some(kind) if kind != line_kind => break,
+                _ => (),
+            }
+            self.pos = line_end;
+        }
+
+        kind.map(|kind| Segment {
+            kind,
+            text: &text[start..self.pos],
+        })
+    }
+}
+
+/// Counts the `>` markers at the start of a line, allowing whitespace
+/// before and between them (`> > text`).
+fn quote_level(line: &str) -> usize {
+    line.split_inclusive('>')
+        .take_while(|marker| marker.trim_start().starts_with('>'))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QuoteSplitter, SegmentKind};
+
+    #[test]
+    fn quote_splitter() {
+        let text = concat!(
+            "Sounds good, see you Monday.\r\n",
+            "\r\n",
+            "> Can we meet on Monday?\r\n",
+            ">> Are you free next week?\r\n",
+            "> > -- \r\n",
+            ">> Bob\r\n",
+            "> \r\n",
+            "> -- \r\n",
+            "> Alice\r\n",
+            "Thanks\r\n",
+            "\r\n",
+            "-- \r\n",
+            "Carol\r\n",
+            "Sent from my phone"
+        );
+
+        assert_eq!(
+            QuoteSplitter::new(text)
+                .map(|segment| segment.kind)
+                .collect::<Vec<_>>(),
+            [
+                SegmentKind::New,
+                SegmentKind::Quoted(1),
+                SegmentKind::Quoted(2),
+                SegmentKind::Quoted(1),
+                SegmentKind::New,
+                SegmentKind::Signature,
+            ]
+        );
+        assert_eq!(
+            QuoteSplitter::new(text)
+                .new_text()
+                .flat_map(|text| text.split_whitespace())
+                .collect::<Vec<_>>(),
+            ["Sounds", "good,", "see", "you", "Monday.", "Thanks"]
+        );
+        assert_eq!(QuoteSplitter::new("").count(), 0);
+    }
+}
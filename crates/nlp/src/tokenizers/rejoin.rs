@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, iter::Peekable};
+
+/// Merges `word`, `-`, `word` token sequences produced by over-splitting
+/// tokenizers back into a single hyphenated token (`e-mail`). Hyphens that
+/// are not surrounded by words are passed through unchanged.
+pub struct RejoinHyphenated<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    iter: Peekable<I>,
+    pending: Vec<Cow<'x, str>>,
+}
+
+impl<'x, I> RejoinHyphenated<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    pub fn new(iter: I) -> Self {
+        RejoinHyphenated {
+            iter: iter.peekable(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<'x, I> Iterator for RejoinHyphenated<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.pop() {
+            return Some(token);
+        }
+
+        let mut token = self.iter.next()?;
+        if is_hyphen(&token) {
+            return Some(token);
+        }
+
+        while self.iter.peek().map_or(false, |t| is_hyphen(t)) {
+            let hyphen = self.iter.next().unwrap();
+            match self.iter.next() {
+                Some(next) if !is_hyphen(&next) => {
+                    token = format!("{token}-{next}").into();
+                }
+                Some(next) => {
+                    self.pending.push(next);
+                    self.pending.push(hyphen);
+                    break;
+                }
+                None => {
+                    self.pending.push(hyphen);
+                    break;
+                }
+            }
+        }
+
+        Some(token)
+    }
+}
+
+#[inline(always)]
+fn is_hyphen(token: &str) -> bool {
+    matches!(token, "-" | "\u{2010}" | "\u{2011}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::RejoinHyphenated;
+
+    #[test]
+    fn rejoin_hyphenated() {
+        for (input, expected) in [
+            (vec!["send", "e", "-", "mail"], vec!["send", "e-mail"]),
+            (vec!["x", "-", "ray", "-", "scan"], vec!["x-ray-scan"]),
+            (vec!["a", "b", "c"], vec!["a", "b", "c"]),
+            (vec!["-", "a", "-"], vec!["-", "a", "-"]),
+            (vec!["a", "-", "-", "b"], vec!["a", "-", "-", "b"]),
+            (vec!["re", "\u{2010}", "sign"], vec!["re-sign"]),
+        ] {
+            assert_eq!(
+                RejoinHyphenated::new(input.iter().copied().map(Cow::from)).collect::<Vec<_>>(),
+                expected,
+                "failed for {input:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use unicode_script::{Script, UnicodeScript};
+
+pub const MIXED_SCRIPT_TOKEN: &str = "__MIXED_SCRIPT__";
+
+/// Splits tokens at Unicode script transitions (`раypal` into `ра` and
+/// `ypal`), emitting `MIXED_SCRIPT_TOKEN` before the pieces of each token
+/// that spans more than one script. Common and inherited characters such as
+/// digits or combining marks belong to the surrounding script. Scripts that
+/// are routinely mixed can be merged, Han, Hiragana and Katakana are merged
+/// by default.
+pub struct ScriptSplitter<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    iter: I,
+    merged: Vec<(Script, Script)>,
+    pending: Vec<Cow<'x, str>>,
+}
+
+impl<'x, I> ScriptSplitter<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    pub fn new(iter: I) -> Self {
+        ScriptSplitter {
+            iter,
+            merged: vec![],
+            pending: vec![],
+        }
+        .merge_scripts(&[Script::Han, Script::Hiragana, Script::Katakana])
+    }
+
+    /// Treats the given scripts as a single one when looking for
+    /// transitions.
+    pub fn merge_scripts(mut self, scripts: &[Script]) -> Self {
+        if let Some((first, rest)) = scripts.split_first() {
+            for script in rest {
+                self.merged.push((*script, *first));
+            }
+        }
+        self
+    }
+
+    fn script_of(&self, ch: char) -> Option<Script> {
+        match ch.script() {
+            Script::Common | Script::Inherited | Script::Unknown => None,
+            script => Some(
+                self.merged
+                    .iter()
+                    .find(|(from, _)| *from == script)
+                    .map_or(script, |(_, to)| *to),
+            ),
+        }
+    }
+}
+
+impl<'x, I> Iterator for ScriptSplitter<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.pop() {
+            return Some(token);
+        }
+
+        let token = self.iter.next()?;
+        let mut boundaries = vec![];
+        let mut current = None;
+
+        for (pos, ch) in token.char_indices() {
+            if let Some(script) = self.script_of(ch) {
+                match current {
+                    Some(current) if current == script => (),
+                    Some(_) => {
+                        boundaries.push(pos);
+                        current = Some(script);
+                    }
+                    None => {
+                        current = Some(script);
+                    }
+                }
+            }
+        }
+
+        if boundaries.is_empty() {
+            return Some(token);
+        }
+
+        boundaries.push(token.len());
+        let mut start = 0;
+        for end in boundaries {
+            self.pending.push(match &token {
+                Cow::Borrowed(token) => Cow::Borrowed(&token[start..end]),
+                Cow::Owned(token) => Cow::Owned(token[start..end].to_string()),
+            });
+            start = end;
+        }
+        self.pending.reverse();
+
+        Some(MIXED_SCRIPT_TOKEN.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use unicode_script::Script;
+
+    use super::{ScriptSplitter, MIXED_SCRIPT_TOKEN};
+
+    #[test]
+    fn script_splitter() {
+        for (input, expected) in [
+            // Cyrillic "ра" followed by Latin "ypal"
+            (
+                vec!["\u{0440}\u{0430}ypal", "login"],
+                vec![MIXED_SCRIPT_TOKEN, "\u{0440}\u{0430}", "ypal", "login"],
+            ),
+            (vec!["paypal", "secure"], vec!["paypal", "secure"]),
+            (vec!["paypal2024", "café"], vec!["paypal2024", "café"]),
+            (
+                vec!["123\u{0430}bc"],
+                vec![MIXED_SCRIPT_TOKEN, "123\u{0430}", "bc"],
+            ),
+            // Japanese mixes scripts by design
+            (vec!["東京タワーへ"], vec!["東京タワーへ"]),
+        ] {
+            assert_eq!(
+                ScriptSplitter::new(input.iter().copied().map(Cow::from)).collect::<Vec<_>>(),
+                expected,
+                "failed for {input:?}"
+            );
+        }
+
+        // Owned tokens and custom merged scripts
+        assert_eq!(
+            ScriptSplitter::new(
+                ["\u{0440}\u{0430}ypal".to_string()]
+                    .into_iter()
+                    .map(Cow::from)
+            )
+            .merge_scripts(&[Script::Latin, Script::Cyrillic])
+            .collect::<Vec<_>>(),
+            vec!["\u{0440}\u{0430}ypal"]
+        );
+        assert_eq!(
+            ScriptSplitter::new(["αβgamma".to_string()].into_iter().map(Cow::from))
+                .collect::<Vec<_>>(),
+            vec![MIXED_SCRIPT_TOKEN, "αβ", "gamma"]
+        );
+    }
+}
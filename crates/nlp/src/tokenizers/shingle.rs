@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, collections::VecDeque};
+
+/// Emits the contiguous word n-grams of every size from 2 to `max_size`,
+/// with their words separated by a space, for comparing plain n-grams
+/// against the skip-bigrams of [`OsbTokenizer`](super::osb::OsbTokenizer).
+/// The shingles starting at each token are emitted from the shortest to the
+/// longest, and tokens near the end of the stream only emit the sizes that
+/// still fit.
+pub struct ShingleTokenizer<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    iter: I,
+    window: VecDeque<Cow<'x, str>>,
+    max_size: usize,
+    min_size: usize,
+    size: usize,
+}
+
+impl<'x, I> ShingleTokenizer<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    pub fn new(iter: I, max_size: usize) -> Self {
+        ShingleTokenizer {
+            iter,
+            window: VecDeque::with_capacity(max_size),
+            max_size,
+            min_size: 2,
+            size: 2,
+        }
+    }
+
+    /// Controls whether each token is also emitted on its own, before its
+    /// shingles.
+    pub fn emit_unigrams(mut self, unigrams: bool) -> Self {
+        self.min_size = if unigrams { 1 } else { 2 };
+        self.size = self.min_size;
+        self
+    }
+}
+
+impl<'x, I> Iterator for ShingleTokenizer<'x, I>
+where
+    I: Iterator<Item = Cow<'x, str>>,
+{
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.window.len() < self.max_size {
+                if let Some(token) = self.iter.next() {
+                    self.window.push_back(token);
+                } else {
+                    break;
+                }
+            }
+
+            if self.size <= self.window.len() {
+                let shingle = if self.size == 1 {
+                    self.window[0].clone()
+                } else {
+                    let mut shingle = String::with_capacity(
+                        self.window
+                            .iter()
+                            .take(self.size)
+                            .map(|t| t.len() + 1)
+                            .sum(),
+                    );
+                    for (pos, token) in self.window.iter().take(self.size).enumerate() {
+                        if pos > 0 {
+                            shingle.push(' ');
+                        }
+                        shingle.push_str(token);
+                    }
+                    shingle.into()
+                };
+                self.size += 1;
+                return Some(shingle);
+            }
+
+            self.window.pop_front()?;
+            self.size = self.min_size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::ShingleTokenizer;
+
+    #[test]
+    fn shingle_tokenizer() {
+        let tokens = || {
+            "The quick brown fox jumps"
+                .split_ascii_whitespace()
+                .map(Cow::from)
+        };
+
+        assert_eq!(
+            ShingleTokenizer::new(tokens(), 3).collect::<Vec<_>>(),
+            [
+                "The quick",
+                "The quick brown",
+                "quick brown",
+                "quick brown fox",
+                "brown fox",
+                "brown fox jumps",
+                "fox jumps",
+            ]
+        );
+        assert_eq!(
+            ShingleTokenizer::new(tokens(), 2)
+                .emit_unigrams(true)
+                .collect::<Vec<_>>(),
+            [
+                "The",
+                "The quick",
+                "quick",
+                "quick brown",
+                "brown",
+                "brown fox",
+                "fox",
+                "fox jumps",
+                "jumps",
+            ]
+        );
+
+        // No skip-bigrams, and nothing to emit from a single token
+        assert!(ShingleTokenizer::new(tokens(), 5).all(|shingle| !shingle.contains("The brown")));
+        assert_eq!(ShingleTokenizer::new(tokens().take(1), 3).count(), 0);
+        assert_eq!(ShingleTokenizer::new(tokens(), 1).count(), 0);
+    }
+}
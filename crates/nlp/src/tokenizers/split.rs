@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, iter::Peekable, str::CharIndices};
+
+use unicode_normalization::char::is_combining_mark;
+
+/// Splits text into words on every character that is not alphanumeric, which
+/// covers the Unicode whitespace (including U+00A0 and U+3000) and
+/// punctuation such as em dashes, and yields them borrowed and unchanged for
+/// [`OsbTokenizer::new`](super::osb::OsbTokenizer::new).
+///
+/// Combining marks stay attached to their word. Hyphens and apostrophes
+/// between two alphanumeric characters don't split, so `e-mail` and `don’t`
+/// are single words while `--` or a trailing `-` are separators.
+pub struct UnicodeWordSplitter<'x> {
+    text: &'x str,
+    iter: Peekable<CharIndices<'x>>,
+}
+
+impl<'x> UnicodeWordSplitter<'x> {
+    pub fn new(text: &'x str) -> Self {
+        UnicodeWordSplitter {
+            text,
+            iter: text.char_indices().peekable(),
+        }
+    }
+}
+
+impl<'x> Iterator for UnicodeWordSplitter<'x> {
+    type Item = Cow<'x, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let text = self.text;
+        let (start, _) = self.iter.find(|(_, ch)| ch.is_alphanumeric())?;
+        let mut end = text.len();
+
+        while let Some(&(pos, ch)) = self.iter.peek() {
+            if ch.is_alphanumeric()
+                || is_combining_mark(ch)
+                || (is_connector(ch)
+                    && text[pos + ch.len_utf8()..]
+                        .chars()
+                        .next()
+                        .map_or(false, char::is_alphanumeric))
+            {
+                self.iter.next();
+            } else {
+                end = pos;
+                break;
+            }
+        }
+
+        Some(Cow::Borrowed(&text[start..end]))
+    }
+}
+
+fn is_connector(ch: char) -> bool {
+    matches!(ch, '-' | '\'' | '\u{2010}' | '\u{2011}' | '\u{2019}')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::tokenizers::osb::OsbTokenizer;
+
+    use super::UnicodeWordSplitter;
+
+    #[test]
+    fn unicode_word_splitter() {
+        let text = "free\u{a0}money\u{3000}now—today, e-mail don’t -- last- 'x' cafe\u{301}!";
+
+        assert_eq!(
+            UnicodeWordSplitter::new(text).collect::<Vec<_>>(),
+            [
+                "free",
+                "money",
+                "now",
+                "today",
+                "e-mail",
+                "don’t",
+                "last",
+                "x",
+                "cafe\u{301}"
+            ]
+        );
+        assert!(UnicodeWordSplitter::new(text).all(|word| matches!(word, Cow::Borrowed(_))));
+        assert_eq!(UnicodeWordSplitter::new(" \u{a0}—\u{3000} ").count(), 0);
+
+        // NBSP separated words form bigrams
+        assert_eq!(
+            OsbTokenizer::<_, String>::new(UnicodeWordSplitter::new("free\u{a0}money"), 2)
+                .map(|t| t.inner)
+                .collect::<Vec<_>>(),
+            ["free", "free money", "money"]
+        );
+    }
+}
@@ -24,6 +24,7 @@
 use directory::backend::internal::manage::ManageDirectory;
 use jmap::mailbox::{INBOX_ID, TRASH_ID};
 use jmap_client::{
+    client::Client,
     core::{
         error::{MethodError, MethodErrorType},
         set::{SetError, SetErrorType},
@@ -31,6 +32,7 @@ use jmap_client::{
     email::{self, import::EmailImportResponse, query::Filter, Property},
     mailbox::{self, Role},
     principal::ACL,
+    sieve::validate::SieveScriptValidateResponse,
 };
 use jmap_proto::types::id::Id;
 use std::fmt::Debug;
@@ -275,6 +277,25 @@ pub async fn test(params: &mut JMAPTest) {
             .await,
     );
 
+    // John should be able to validate scripts stored in blobs shared with him
+    let shared_blob_id = jane_client
+        .email_get(
+            email_ids.get("jane").unwrap().first().unwrap(),
+            [Property::BlobId].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .take_blob_id();
+    match validate_script(&mut john_client, &john_id, &shared_blob_id).await {
+        Err(jmap_client::Error::Set(SetError {
+            type_: SetErrorType::InvalidScript,
+            ..
+        })) => (),
+        result => panic!("Expected invalidScript, got {:?}", result),
+    }
+    assert_forbidden(validate_script(&mut john_client, &john_id, &blob_id).await);
+
     // John only has ReadItems access to Inbox but no Read access
     assert_forbidden(
         john_client
@@ -802,6 +823,21 @@ pub async fn test(params: &mut JMAPTest) {
     assert_is_empty(server).await;
 }
 
+async fn validate_script(
+    client: &mut Client,
+    account_id: &Id,
+    blob_id: &str,
+) -> Result<(), jmap_client::Error> {
+    let mut request = client
+        .set_default_account_id(account_id.to_string())
+        .build();
+    request.validate_sieve_script(blob_id);
+    request
+        .send_single::<SieveScriptValidateResponse>()
+        .await?
+        .unwrap_error()
+}
+
 pub fn assert_forbidden<T: Debug>(result: Result<T, jmap_client::Error>) {
     if !matches!(
         result,